@@ -1,7 +1,10 @@
 #pragma once
 
+#include <stdbool.h>
 #include <stdint.h>
 #include <stddef.h>
+#include <stdio.h>
+#include <sys/types.h>
 
 typedef size_t codespan_file_id;
 typedef size_t codespan_byte_index;
@@ -36,6 +39,47 @@ CodespanSourceMap *codespan_new_source_map(void *user_data,
                                            codespan_line_number_callback,
                                            codespan_column_number_callback);
 
+#define CODESPAN_FILE_ID_INVALID SIZE_MAX
+
+CodespanSourceMap *codespan_new_filesystem_source_map(void);
+codespan_file_id codespan_source_map_add_path(const CodespanSourceMap *source_map,
+                                              const uint8_t *path,
+                                              size_t path_len);
+void codespan_source_map_set_mmap_threshold(CodespanSourceMap *source_map,
+                                            size_t threshold);
+
+CodespanSourceMap *codespan_new_overlay_source_map(const CodespanSourceMap *base);
+bool codespan_source_map_set_overlay(CodespanSourceMap *source_map,
+                                     const uint8_t *path,
+                                     size_t path_len,
+                                     const uint8_t *source,
+                                     size_t source_len);
+bool codespan_source_map_clear_overlay(CodespanSourceMap *source_map,
+                                       const uint8_t *path,
+                                       size_t path_len);
+
+typedef uint64_t codespan_version;
+
+typedef struct CodespanTextEdit {
+    codespan_byte_index start;
+    codespan_byte_index end;
+    const uint8_t *text;
+    size_t text_len;
+} CodespanTextEdit;
+
+CodespanSourceMap *codespan_new_owned_source_map(void);
+codespan_file_id codespan_source_map_add_file(CodespanSourceMap *source_map,
+                                              const uint8_t *name,
+                                              size_t name_len,
+                                              const uint8_t *source,
+                                              size_t source_len);
+bool codespan_source_map_apply_edits(CodespanSourceMap *source_map,
+                                     codespan_file_id file_id,
+                                     const CodespanTextEdit *edits,
+                                     size_t edit_count);
+codespan_version codespan_source_map_file_version(const CodespanSourceMap *source_map,
+                                                  codespan_file_id file_id);
+
 void codespan_delete_source_map(CodespanSourceMap* source_map);
 
 typedef size_t codespan_severity;
@@ -45,10 +89,29 @@ typedef size_t codespan_severity;
 #define CODESPAN_SEVERITY_ERROR 3
 #define CODESPAN_SEVERITY_BUG 4
 
+typedef uint8_t codespan_color_mode;
+#define CODESPAN_COLOR_NEVER 0
+#define CODESPAN_COLOR_ALWAYS 1
+#define CODESPAN_COLOR_AUTO 2
+
 typedef void(*codespan_writer_callback)(void *user_data,
                                         const uint8_t *utf8_output,
                                         size_t output_len);
 
+typedef int codespan_status;
+#define CODESPAN_STATUS_OK 0
+#define CODESPAN_STATUS_INVALID_ARGUMENT -1
+#define CODESPAN_STATUS_FILE_MISSING -2
+#define CODESPAN_STATUS_INVALID_INDEX -3
+#define CODESPAN_STATUS_IO_ERROR -4
+#define CODESPAN_STATUS_FORMAT_ERROR -5
+#define CODESPAN_STATUS_FILTERED -6
+
+typedef int(*codespan_stream_writer_callback)(void *user_data,
+                                              const uint8_t *utf8_output,
+                                              size_t output_len);
+typedef int(*codespan_flush_callback)(void *user_data);
+
 typedef struct CodespanDiagnostic CodespanDiagnostic;
 
 CodespanDiagnostic *codespan_new_diagnostic(codespan_severity severity,
@@ -59,7 +122,31 @@ void codespan_delete_diagnostic(CodespanDiagnostic *diagnostic);
 void codespan_write_diagnostic(void* user_data,
                                const CodespanDiagnostic *diagnostic,
                                const CodespanSourceMap *src_map,
-                               uint8_t color);
+                               codespan_color_mode color);
+codespan_status codespan_write_diagnostic_stream(void *user_data,
+                                                const CodespanDiagnostic *diagnostic,
+                                                const CodespanSourceMap *src_map,
+                                                codespan_color_mode color,
+                                                codespan_stream_writer_callback,
+                                                codespan_flush_callback);
+codespan_status codespan_write_diagnostic_fd(int fd,
+                                            const CodespanDiagnostic *diagnostic,
+                                            const CodespanSourceMap *src_map,
+                                            codespan_color_mode color);
+codespan_status codespan_write_diagnostic_file(FILE *file,
+                                              const CodespanDiagnostic *diagnostic,
+                                              const CodespanSourceMap *src_map,
+                                              codespan_color_mode color);
+uint8_t *codespan_render_diagnostic_to_string(const CodespanDiagnostic *diagnostic,
+                                              const CodespanSourceMap *src_map,
+                                              codespan_color_mode color,
+                                              size_t *output_len);
+void codespan_free_string(uint8_t *string);
+ssize_t codespan_render_diagnostic_to_buffer(const CodespanDiagnostic *diagnostic,
+                                             const CodespanSourceMap *src_map,
+                                             codespan_color_mode color,
+                                             uint8_t *buffer,
+                                             size_t buffer_size);
 void codespan_diagnostic_set_code(const CodespanDiagnostic* diagnostic,
                                   const uint8_t *code,
                                   size_t code_len);
@@ -92,3 +179,250 @@ void codespan_diagnostic_set_config(CodespanDiagnostic *diagnostic,
                                     codespan_display_style display_style,
                                     codespan_char_style char_style,
                                     size_t tab_width);
+
+typedef struct CodespanConfig CodespanConfig;
+
+typedef size_t codespan_theme;
+#define CODESPAN_THEME_DEFAULT 0
+#define CODESPAN_THEME_LIGHT 1
+#define CODESPAN_THEME_MONO 2
+
+CodespanConfig *codespan_new_config(void);
+void codespan_delete_config(CodespanConfig *config);
+bool codespan_config_set_style(CodespanConfig *config,
+                               codespan_display_style display_style,
+                               codespan_char_style char_style,
+                               size_t tab_width);
+bool codespan_config_set_color(CodespanConfig *config,
+                               codespan_color_mode color);
+codespan_color_mode codespan_config_color(const CodespanConfig *config);
+bool codespan_config_set_theme(CodespanConfig *config,
+                               codespan_theme theme);
+bool codespan_config_apply_env(void *user_data,
+                               CodespanConfig *config,
+                               codespan_writer_callback);
+void codespan_diagnostic_apply_config(CodespanDiagnostic *diagnostic,
+                                      const CodespanConfig *config);
+
+typedef size_t codespan_output_format;
+#define CODESPAN_OUTPUT_FORMAT_TERM 0
+#define CODESPAN_OUTPUT_FORMAT_GITHUB_ACTIONS 1
+#define CODESPAN_OUTPUT_FORMAT_RUSTC_JSON 2
+#define CODESPAN_OUTPUT_FORMAT_GNU 3
+#define CODESPAN_OUTPUT_FORMAT_MSVC 4
+
+typedef size_t codespan_format_options;
+#define CODESPAN_FORMAT_OPTION_GITHUB_GROUP (1 << 0)
+#define CODESPAN_FORMAT_OPTION_RANGES (1 << 1)
+#define CODESPAN_FORMAT_OPTION_SECONDARY_NOTES (1 << 2)
+
+void codespan_diagnostic_set_format(CodespanDiagnostic *diagnostic,
+                                    codespan_output_format format,
+                                    codespan_format_options options);
+
+typedef size_t codespan_rebase_mode;
+#define CODESPAN_REBASE_MODE_DROP 0
+#define CODESPAN_REBASE_MODE_FLAG 1
+
+codespan_status codespan_diagnostic_rebase(CodespanDiagnostic *diagnostic,
+                                           codespan_file_id file_id,
+                                           const CodespanTextEdit *edits,
+                                           size_t edit_count,
+                                           codespan_rebase_mode mode,
+                                           size_t *edited);
+
+typedef struct CodespanToolInfo {
+    const uint8_t *name;
+    size_t name_len;
+    const uint8_t *version;
+    size_t version_len;
+    const uint8_t *information_uri;
+    size_t information_uri_len;
+} CodespanToolInfo;
+
+void codespan_write_sarif(void *user_data,
+                          const CodespanDiagnostic *const *diagnostics,
+                          size_t diagnostics_len,
+                          const CodespanSourceMap *src_map,
+                          const CodespanToolInfo *tool,
+                          codespan_writer_callback);
+
+void codespan_write_lsp_diagnostics(void *user_data,
+                                    const CodespanDiagnostic *diagnostic,
+                                    const CodespanSourceMap *src_map,
+                                    const uint8_t *source,
+                                    size_t source_len,
+                                    codespan_writer_callback);
+
+void codespan_write_gitlab_code_quality(void *user_data,
+                                        const CodespanDiagnostic *const *diagnostics,
+                                        size_t diagnostics_len,
+                                        const CodespanSourceMap *src_map,
+                                        codespan_writer_callback);
+
+void codespan_write_checkstyle(void *user_data,
+                               const CodespanDiagnostic *const *diagnostics,
+                               size_t diagnostics_len,
+                               const CodespanSourceMap *src_map,
+                               codespan_writer_callback);
+void codespan_write_junit(void *user_data,
+                          const CodespanDiagnostic *const *diagnostics,
+                          size_t diagnostics_len,
+                          const CodespanSourceMap *src_map,
+                          const uint8_t *suite_name,
+                          size_t suite_name_len,
+                          codespan_writer_callback);
+
+codespan_status codespan_diagnostic_snapshot(CodespanDiagnostic *diagnostic,
+                                             const CodespanSourceMap *src_map);
+
+typedef size_t codespan_stale_mode;
+#define CODESPAN_STALE_MODE_NOTE 0
+#define CODESPAN_STALE_MODE_NO_SNIPPET 1
+
+codespan_status codespan_diagnostic_record_sources(CodespanDiagnostic *diagnostic,
+                                                   const CodespanSourceMap *src_map,
+                                                   codespan_stale_mode mode);
+bool codespan_diagnostic_is_stale(const CodespanDiagnostic *diagnostic,
+                                  const CodespanSourceMap *src_map);
+
+typedef uint64_t codespan_fingerprint;
+
+codespan_fingerprint codespan_diagnostic_fingerprint(const CodespanDiagnostic *diagnostic,
+                                                     const CodespanSourceMap *src_map);
+
+typedef struct CodespanBaseline CodespanBaseline;
+
+CodespanBaseline *codespan_new_baseline(void);
+void codespan_delete_baseline(CodespanBaseline *baseline);
+bool codespan_baseline_load(CodespanBaseline *baseline,
+                            const uint8_t *data,
+                            size_t data_len);
+void codespan_baseline_write_unused(void *user_data,
+                                    const CodespanBaseline *baseline,
+                                    codespan_writer_callback);
+size_t codespan_baseline_unused_count(const CodespanBaseline *baseline);
+
+typedef struct CodespanEmitter CodespanEmitter;
+
+typedef size_t codespan_baseline_mode;
+#define CODESPAN_BASELINE_MODE_SUPPRESS 0
+#define CODESPAN_BASELINE_MODE_DOWNGRADE 1
+
+CodespanEmitter *codespan_new_emitter(const CodespanSourceMap *src_map);
+void codespan_delete_emitter(CodespanEmitter *emitter);
+void codespan_emitter_set_baseline(CodespanEmitter *emitter,
+                                   CodespanBaseline *baseline,
+                                   codespan_baseline_mode mode);
+codespan_status codespan_emitter_emit(void *user_data,
+                                      CodespanEmitter *emitter,
+                                      const CodespanDiagnostic *diagnostic,
+                                      codespan_color_mode color);
+codespan_status codespan_emitter_write_baseline(void *user_data,
+                                                const CodespanEmitter *emitter,
+                                                codespan_writer_callback);
+
+typedef struct CodespanSuppressionSyntax {
+    const uint8_t *line_comment;
+    size_t line_comment_len;
+    const uint8_t *next_line_marker;
+    size_t next_line_marker_len;
+    const uint8_t *same_line_marker;
+    size_t same_line_marker_len;
+    const uint8_t *file_marker;
+    size_t file_marker_len;
+} CodespanSuppressionSyntax;
+
+void codespan_emitter_set_default_suppression_syntax(CodespanEmitter *emitter,
+                                                     const CodespanSuppressionSyntax *syntax);
+void codespan_emitter_set_suppression_syntax(CodespanEmitter *emitter,
+                                             codespan_file_id file_id,
+                                             const CodespanSuppressionSyntax *syntax);
+void codespan_emitter_scan_suppressions(CodespanEmitter *emitter,
+                                        codespan_file_id file_id);
+codespan_status codespan_emitter_write_unused_suppressions(void *user_data,
+                                                           const CodespanEmitter *emitter,
+                                                           codespan_color_mode color,
+                                                           codespan_writer_callback);
+
+typedef size_t codespan_dedup_mode;
+#define CODESPAN_DEDUP_MODE_NONE 0
+#define CODESPAN_DEDUP_MODE_EXACT 1
+#define CODESPAN_DEDUP_MODE_PRIMARY_SPAN 2
+
+void codespan_emitter_set_dedup_mode(CodespanEmitter *emitter,
+                                     codespan_dedup_mode mode);
+size_t codespan_emitter_deduplicated_count(const CodespanEmitter *emitter);
+
+typedef struct CodespanJob CodespanJob;
+
+typedef size_t codespan_job_order;
+#define CODESPAN_JOB_ORDER_INDEX 0
+#define CODESPAN_JOB_ORDER_COMMIT 1
+
+void codespan_emitter_set_job_order(CodespanEmitter *emitter,
+                                    codespan_job_order order);
+CodespanJob *codespan_emitter_begin_job(CodespanEmitter *emitter,
+                                        size_t job_index);
+void codespan_emitter_flush(CodespanEmitter *emitter);
+codespan_status codespan_job_emit(void *user_data,
+                                  CodespanJob *job,
+                                  const CodespanDiagnostic *diagnostic,
+                                  codespan_color_mode color);
+void codespan_job_commit(CodespanJob *job);
+
+typedef struct CodespanSink CodespanSink;
+
+CodespanSink *codespan_new_sink(void *user_data,
+                                codespan_output_format format,
+                                codespan_format_options options,
+                                codespan_color_mode color,
+                                codespan_writer_callback);
+CodespanSink *codespan_new_sarif_sink(void *user_data,
+                                      const CodespanToolInfo *tool,
+                                      codespan_writer_callback);
+CodespanSink *codespan_new_gitlab_code_quality_sink(void *user_data,
+                                                    codespan_writer_callback);
+CodespanSink *codespan_new_checkstyle_sink(void *user_data,
+                                           codespan_writer_callback);
+CodespanSink *codespan_new_junit_sink(void *user_data,
+                                      const uint8_t *suite_name,
+                                      size_t suite_name_len,
+                                      codespan_writer_callback);
+void codespan_delete_sink(CodespanSink *sink);
+void codespan_sink_set_config(CodespanSink *sink,
+                              codespan_display_style display_style,
+                              codespan_char_style char_style,
+                              size_t tab_width);
+void codespan_sink_apply_config(CodespanSink *sink,
+                                const CodespanConfig *config);
+void codespan_emitter_add_sink(CodespanEmitter *emitter,
+                               CodespanSink *sink);
+void codespan_emitter_finish(CodespanEmitter *emitter);
+
+typedef struct CodespanDiagnosticList CodespanDiagnosticList;
+
+typedef size_t codespan_sort_order;
+#define CODESPAN_SORT_ORDER_POSITION 0
+#define CODESPAN_SORT_ORDER_SEVERITY 1
+
+typedef size_t codespan_list_options;
+#define CODESPAN_LIST_OPTION_GROUP_BY_FILE (1 << 0)
+#define CODESPAN_LIST_OPTION_FILE_HEADERS (1 << 1)
+
+CodespanDiagnosticList *codespan_new_diagnostic_list(void);
+void codespan_delete_diagnostic_list(CodespanDiagnosticList *list);
+void codespan_diagnostic_list_push(CodespanDiagnosticList *list,
+                                   CodespanDiagnostic *diagnostic);
+size_t codespan_diagnostic_list_length(const CodespanDiagnosticList *list);
+const CodespanDiagnostic *codespan_diagnostic_list_get(const CodespanDiagnosticList *list,
+                                                       size_t index);
+void codespan_diagnostic_list_sort(CodespanDiagnosticList *list,
+                                   const CodespanSourceMap *src_map,
+                                   codespan_sort_order order);
+void codespan_diagnostic_list_write(void *user_data,
+                                    const CodespanDiagnosticList *list,
+                                    const CodespanSourceMap *src_map,
+                                    codespan_color_mode color,
+                                    codespan_list_options options,
+                                    codespan_writer_callback);
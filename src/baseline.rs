@@ -0,0 +1,192 @@
+use crate::diagnostic::{CodespanDiagnostic, WriterCallback};
+use crate::fingerprint::{self, Fingerprint};
+use crate::output;
+use crate::source_map::CodespanSourceMap;
+use codespan_reporting::files::Files;
+use std::fmt::Write;
+use std::{slice, str};
+
+const BASELINE_HEADER: &str = "# codespan baseline v1";
+
+#[derive(Clone)]
+pub(crate) struct BaselineEntry {
+    pub(crate) fingerprint: Fingerprint,
+    code: String,
+    file_name: String,
+    message: String,
+}
+
+impl BaselineEntry {
+    pub(crate) fn new(diagnostic: &CodespanDiagnostic, source_map: &CodespanSourceMap) -> Self {
+        let diagnostic = &diagnostic.diagnostic;
+        Self {
+            fingerprint: fingerprint::fingerprint(diagnostic, source_map),
+            code: diagnostic.code.clone().unwrap_or_default(),
+            file_name: output::primary_label(diagnostic)
+                .and_then(|label| source_map.name(label.file_id).ok())
+                .unwrap_or_default()
+                .to_owned(),
+            message: diagnostic.message.clone(),
+        }
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(4, '\t');
+        let fingerprint = Fingerprint::from_str_radix(fields.next()?, 16).ok()?;
+        Some(Self {
+            fingerprint,
+            code: unescape(fields.next().unwrap_or_default()),
+            file_name: unescape(fields.next().unwrap_or_default()),
+            message: unescape(fields.next().unwrap_or_default()),
+        })
+    }
+}
+
+pub(crate) fn write_entries<'a>(entries: impl Iterator<Item = &'a BaselineEntry>) -> String {
+    let mut baseline = format!("{BASELINE_HEADER}\n");
+    for entry in entries {
+        writeln!(
+            baseline,
+            "{:016x}\t{}\t{}\t{}",
+            entry.fingerprint,
+            escape(&entry.code),
+            escape(&entry.file_name),
+            escape(&entry.message)
+        )
+        .unwrap();
+    }
+    baseline
+}
+
+fn escape(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(field: &str) -> String {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('t')) => unescaped.push('\t'),
+            ('\\', Some('n')) => unescaped.push('\n'),
+            ('\\', Some('\\')) => unescaped.push('\\'),
+            _ => {
+                unescaped.push(c);
+                continue;
+            }
+        }
+        chars.next();
+    }
+    unescaped
+}
+
+pub struct CodespanBaseline {
+    entries: Vec<BaselineEntry>,
+    used: Vec<bool>,
+}
+
+impl CodespanBaseline {
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_new_baseline() -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            entries: Vec::new(),
+            used: Vec::new(),
+        }))
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_delete_baseline(baseline: *mut Self) {
+        if !baseline.is_null() {
+            unsafe { drop(Box::from_raw(baseline)) }
+        }
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_baseline_load(
+        baseline: *mut Self,
+        data: *const u8,
+        data_length: libc::size_t,
+    ) -> bool {
+        if baseline.is_null() {
+            return false;
+        }
+
+        let baseline = unsafe { &mut *baseline };
+        let data = if data.is_null() {
+            ""
+        } else {
+            match str::from_utf8(unsafe { slice::from_raw_parts(data, data_length) }) {
+                Ok(data) => data,
+                Err(_) => return false,
+            }
+        };
+        let entries = data
+            .lines()
+            .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+            .map(BaselineEntry::parse)
+            .collect::<Option<Vec<_>>>();
+
+        match entries {
+            Some(entries) => {
+                baseline.used.extend(entries.iter().map(|_| false));
+                baseline.entries.extend(entries);
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_baseline_write_unused(
+        user_data: *mut libc::c_void,
+        baseline: *const Self,
+        writer: Option<WriterCallback>,
+    ) {
+        if !baseline.is_null() {
+            let baseline = unsafe { &*baseline };
+            let utf8_output = write_entries(baseline.unused());
+            unsafe { (writer.unwrap())(user_data, utf8_output.as_ptr(), utf8_output.len()) };
+        }
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_baseline_unused_count(baseline: *const Self) -> libc::size_t {
+        if baseline.is_null() {
+            0
+        } else {
+            unsafe { &*baseline }.unused().count()
+        }
+    }
+
+    pub(crate) fn contains(&self, fingerprint: Fingerprint) -> bool {
+        self.position(fingerprint).is_some()
+    }
+
+    // Each entry stands for a single occurrence, so a diagnostic reported
+    // twice needs to be in the baseline twice.
+    pub(crate) fn take(&mut self, fingerprint: Fingerprint) -> bool {
+        let position = self.position(fingerprint);
+        if let Some(position) = position {
+            self.used[position] = true;
+        }
+        position.is_some()
+    }
+
+    fn position(&self, fingerprint: Fingerprint) -> Option<usize> {
+        self.entries
+            .iter()
+            .zip(&self.used)
+            .position(|(entry, used)| !used && entry.fingerprint == fingerprint)
+    }
+
+    fn unused(&self) -> impl Iterator<Item = &BaselineEntry> {
+        self.entries
+            .iter()
+            .zip(&self.used)
+            .filter(|(_, used)| !**used)
+            .map(|(entry, _)| entry)
+    }
+}
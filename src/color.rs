@@ -0,0 +1,37 @@
+use std::env;
+use std::ffi::OsString;
+
+pub type ColorMode = u8;
+pub const COLOR_NEVER: ColorMode = 0;
+pub const COLOR_ALWAYS: ColorMode = 1;
+pub const COLOR_AUTO: ColorMode = 2;
+
+// Decides whether output written to `fd` should be colored. Callback writers
+// have no file descriptor, so in auto mode they are only colored when forced
+// through the environment.
+pub(crate) fn enabled(color: ColorMode, fd: Option<libc::c_int>) -> bool {
+    match color {
+        COLOR_NEVER => false,
+        COLOR_AUTO => detect(fd, |name| env::var_os(name)),
+        _ => true,
+    }
+}
+
+// Takes the environment lookup as a parameter so that it can be tested
+// without changing the process environment.
+pub(crate) fn detect(fd: Option<libc::c_int>, lookup: impl Fn(&str) -> Option<OsString>) -> bool {
+    let var = |name| lookup(name).filter(|value| !value.is_empty());
+
+    if var("CLICOLOR_FORCE").is_some_and(|value| value != "0")
+        || var("FORCE_COLOR").is_some_and(|value| value != "0" && value != "false")
+    {
+        return true;
+    }
+    if var("NO_COLOR").is_some()
+        || var("CLICOLOR").is_some_and(|value| value == "0")
+        || var("TERM").is_some_and(|value| value == "dumb")
+    {
+        return false;
+    }
+    fd.is_some_and(|fd| unsafe { libc::isatty(fd) } == 1)
+}
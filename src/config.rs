@@ -0,0 +1,248 @@
+use crate::color::{ColorMode, COLOR_ALWAYS, COLOR_AUTO, COLOR_NEVER};
+use crate::diagnostic::{
+    CharStyle, CodespanDiagnostic, DisplayStyle, WriterCallback, CHAR_STYLE_ASCII,
+    CHAR_STYLE_FANCY, DISPLAY_STYLE_MEDIUM, DISPLAY_STYLE_RICH, DISPLAY_STYLE_SHORT,
+};
+use codespan_reporting as csr;
+use codespan_reporting::term::termcolor::{Color, ColorSpec};
+use std::env;
+use std::fmt::Write;
+
+pub type Theme = libc::size_t;
+pub const THEME_DEFAULT: Theme = 0;
+pub const THEME_LIGHT: Theme = 1;
+pub const THEME_MONO: Theme = 2;
+
+#[derive(Clone)]
+pub struct CodespanConfig {
+    pub(crate) config: csr::term::Config,
+    pub(crate) styles: csr::term::Styles,
+    pub(crate) color: ColorMode,
+}
+
+impl CodespanConfig {
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_new_config() -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            config: csr::term::Config::default(),
+            styles: csr::term::Styles::default(),
+            color: COLOR_AUTO,
+        }))
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_delete_config(config: *mut Self) {
+        if !config.is_null() {
+            unsafe { drop(Box::from_raw(config)) }
+        }
+    }
+
+    // Unlike `codespan_diagnostic_set_config`, unknown values are rejected
+    // and leave the config unchanged.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_config_set_style(
+        config: *mut Self,
+        style: DisplayStyle,
+        chars: CharStyle,
+        tab_width: libc::size_t,
+    ) -> bool {
+        let valid_style = matches!(
+            style,
+            DISPLAY_STYLE_RICH | DISPLAY_STYLE_MEDIUM | DISPLAY_STYLE_SHORT
+        );
+        let valid_chars = matches!(chars, CHAR_STYLE_FANCY | CHAR_STYLE_ASCII);
+        if config.is_null() || !valid_style || !valid_chars || tab_width == 0 {
+            return false;
+        }
+
+        let config = unsafe { &mut *config };
+        CodespanDiagnostic::apply_config(&mut config.config, style, chars, tab_width);
+        true
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_config_set_color(
+        config: *mut Self,
+        color: ColorMode,
+    ) -> bool {
+        if config.is_null() || !matches!(color, COLOR_NEVER | COLOR_ALWAYS | COLOR_AUTO) {
+            return false;
+        }
+
+        unsafe { &mut *config }.color = color;
+        true
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_config_color(config: *const Self) -> ColorMode {
+        if config.is_null() {
+            COLOR_AUTO
+        } else {
+            unsafe { &*config }.color
+        }
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_config_set_theme(config: *mut Self, theme: Theme) -> bool {
+        match (config.is_null(), styles(theme)) {
+            (false, Some(styles)) => {
+                unsafe { &mut *config }.styles = styles;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Applies the `CODESPAN_*` environment variables on top of the current
+    // settings. Invalid values are ignored and reported through `writer`.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_config_apply_env(
+        user_data: *mut libc::c_void,
+        config: *mut Self,
+        writer: Option<WriterCallback>,
+    ) -> bool {
+        if config.is_null() {
+            return false;
+        }
+
+        let errors = unsafe { &mut *config }.apply_env(|name| env::var(name).ok());
+        if errors.is_empty() {
+            return true;
+        }
+        if let Some(writer) = writer {
+            unsafe { writer(user_data, errors.as_ptr(), errors.len()) };
+        }
+        false
+    }
+
+    // Applies CODESPAN_STYLE, CODESPAN_CHARS, CODESPAN_TAB_WIDTH,
+    // CODESPAN_COLOR and CODESPAN_THEME as read through `lookup`. Unset and
+    // empty variables are ignored, and invalid values are left out and
+    // reported in the returned messages, one line each.
+    pub(crate) fn apply_env(&mut self, lookup: impl Fn(&str) -> Option<String>) -> String {
+        let mut errors = String::new();
+
+        if let Some(style) = env_var(&mut errors, &lookup, "CODESPAN_STYLE", parse_display_style) {
+            self.config.display_style = style;
+        }
+        if let Some(chars) = env_var(&mut errors, &lookup, "CODESPAN_CHARS", parse_chars) {
+            self.config.chars = chars;
+        }
+        if let Some(tab_width) =
+            env_var(&mut errors, &lookup, "CODESPAN_TAB_WIDTH", parse_tab_width)
+        {
+            self.config.tab_width = tab_width;
+        }
+        if let Some(color) = env_var(&mut errors, &lookup, "CODESPAN_COLOR", parse_color) {
+            self.color = color;
+        }
+        if let Some(styles) = env_var(&mut errors, &lookup, "CODESPAN_THEME", parse_theme) {
+            self.styles = styles;
+        }
+        errors
+    }
+}
+
+// Unset and empty variables are ignored. Parsers return the expected values
+// as the error.
+fn env_var<T>(
+    errors: &mut String,
+    lookup: &impl Fn(&str) -> Option<String>,
+    name: &str,
+    parse: fn(&str) -> Result<T, &'static str>,
+) -> Option<T> {
+    let value = lookup(name).filter(|value| !value.is_empty())?;
+    parse(value.trim())
+        .inspect_err(|expected| {
+            writeln!(
+                errors,
+                "invalid value `{value}` for {name}, expected {expected}"
+            )
+            .unwrap()
+        })
+        .ok()
+}
+
+fn parse_display_style(value: &str) -> Result<csr::term::DisplayStyle, &'static str> {
+    match value {
+        "rich" => Ok(csr::term::DisplayStyle::Rich),
+        "medium" => Ok(csr::term::DisplayStyle::Medium),
+        "short" => Ok(csr::term::DisplayStyle::Short),
+        _ => Err("rich, medium or short"),
+    }
+}
+
+fn parse_chars(value: &str) -> Result<csr::term::Chars, &'static str> {
+    match value {
+        "fancy" => Ok(csr::term::Chars::default()),
+        "ascii" => Ok(csr::term::Chars::ascii()),
+        _ => Err("fancy or ascii"),
+    }
+}
+
+fn parse_tab_width(value: &str) -> Result<usize, &'static str> {
+    value
+        .parse()
+        .ok()
+        .filter(|tab_width| *tab_width > 0)
+        .ok_or("a positive integer")
+}
+
+fn parse_color(value: &str) -> Result<ColorMode, &'static str> {
+    match value {
+        "never" => Ok(COLOR_NEVER),
+        "always" => Ok(COLOR_ALWAYS),
+        "auto" => Ok(COLOR_AUTO),
+        _ => Err("never, always or auto"),
+    }
+}
+
+fn parse_theme(value: &str) -> Result<csr::term::Styles, &'static str> {
+    let theme = match value {
+        "default" => THEME_DEFAULT,
+        "light" => THEME_LIGHT,
+        "mono" => THEME_MONO,
+        _ => return Err("default, light or mono"),
+    };
+    Ok(styles(theme).unwrap())
+}
+
+fn styles(theme: Theme) -> Option<csr::term::Styles> {
+    let fg = |color| ColorSpec::new().set_fg(Some(color)).clone();
+    match theme {
+        THEME_DEFAULT => Some(csr::term::Styles::default()),
+        // The default cyan and yellow are hard to read on a light background.
+        THEME_LIGHT => {
+            let header = ColorSpec::new().set_bold(true).clone();
+            Some(csr::term::Styles {
+                header_bug: header.clone().set_fg(Some(Color::Red)).clone(),
+                header_error: header.clone().set_fg(Some(Color::Red)).clone(),
+                header_warning: header.clone().set_fg(Some(Color::Magenta)).clone(),
+                header_note: header.clone().set_fg(Some(Color::Green)).clone(),
+                header_help: header.clone().set_fg(Some(Color::Blue)).clone(),
+                header_message: header,
+                primary_label_bug: fg(Color::Red),
+                primary_label_error: fg(Color::Red),
+                primary_label_warning: fg(Color::Magenta),
+                primary_label_note: fg(Color::Green),
+                primary_label_help: fg(Color::Blue),
+                secondary_label: fg(Color::Blue),
+                line_number: fg(Color::Blue),
+                source_border: fg(Color::Blue),
+                note_bullet: fg(Color::Blue),
+            })
+        }
+        THEME_MONO => {
+            let bold = ColorSpec::new().set_bold(true).clone();
+            let mut styles = csr::term::Styles::no_color();
+            styles.header_bug = bold.clone();
+            styles.header_error = bold.clone();
+            styles.header_warning = bold.clone();
+            styles.header_note = bold.clone();
+            styles.header_help = bold.clone();
+            styles.header_message = bold;
+            Some(styles)
+        }
+        _ => None,
+    }
+}
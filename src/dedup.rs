@@ -0,0 +1,79 @@
+use crate::fingerprint::Fnv1a;
+use crate::output;
+use crate::FileId;
+use codespan_reporting::diagnostic as csr_diag;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+pub type DedupMode = libc::size_t;
+pub const DEDUP_MODE_NONE: DedupMode = 0;
+pub const DEDUP_MODE_EXACT: DedupMode = 1;
+pub const DEDUP_MODE_PRIMARY_SPAN: DedupMode = 2;
+
+#[derive(Default)]
+pub(crate) struct Deduplicator {
+    exact: HashMap<u64, Vec<csr_diag::Diagnostic<FileId>>>,
+    primary_spans: HashSet<(FileId, Range<usize>, Option<String>)>,
+    pub(crate) count: usize,
+}
+
+impl Deduplicator {
+    pub(crate) fn is_duplicate(
+        &self,
+        mode: DedupMode,
+        diagnostic: &csr_diag::Diagnostic<FileId>,
+    ) -> bool {
+        match (mode, output::primary_label(diagnostic)) {
+            (DEDUP_MODE_NONE, _) => false,
+            (DEDUP_MODE_PRIMARY_SPAN, Some(label)) => self.primary_spans.contains(&(
+                label.file_id,
+                label.range.clone(),
+                diagnostic.code.clone(),
+            )),
+            // Diagnostics without a primary label have no span to compare, so
+            // they are only dropped when they are identical.
+            _ => self
+                .exact
+                .get(&hash(diagnostic))
+                .is_some_and(|seen| seen.contains(diagnostic)),
+        }
+    }
+
+    // Remembers an emitted diagnostic so that later duplicates are dropped.
+    pub(crate) fn insert(&mut self, mode: DedupMode, diagnostic: &csr_diag::Diagnostic<FileId>) {
+        match (mode, output::primary_label(diagnostic)) {
+            (DEDUP_MODE_NONE, _) => {}
+            (DEDUP_MODE_PRIMARY_SPAN, Some(label)) => {
+                self.primary_spans.insert((
+                    label.file_id,
+                    label.range.clone(),
+                    diagnostic.code.clone(),
+                ));
+            }
+            _ => {
+                let seen = self.exact.entry(hash(diagnostic)).or_default();
+                if !seen.contains(diagnostic) {
+                    seen.push(diagnostic.clone());
+                }
+            }
+        }
+    }
+}
+
+fn hash(diagnostic: &csr_diag::Diagnostic<FileId>) -> u64 {
+    let mut hasher = Fnv1a::new();
+    hasher.write(&[diagnostic.severity as u8]);
+    hasher.write_field(diagnostic.code.as_deref().unwrap_or_default());
+    hasher.write_field(&diagnostic.message);
+    for label in &diagnostic.labels {
+        hasher.write(&[label.style as u8]);
+        hasher.write(&label.file_id.to_le_bytes());
+        hasher.write(&label.range.start.to_le_bytes());
+        hasher.write(&label.range.end.to_le_bytes());
+        hasher.write_field(&label.message);
+    }
+    for note in &diagnostic.notes {
+        hasher.write_field(note);
+    }
+    hasher.finish()
+}
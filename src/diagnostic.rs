@@ -1,8 +1,12 @@
-use crate::{source_map::CodespanSourceMap, ByteIndex, FileId};
+use crate::color::{self, ColorMode};
+use crate::config::CodespanConfig;
+use crate::staleness::SourceRecord;
+use crate::{output, source_map::CodespanSourceMap, ByteIndex, FileId};
 use codespan_reporting as csr;
 use codespan_reporting::diagnostic as csr_diag;
 use codespan_reporting::term::termcolor;
 use std::slice;
+use std::sync::Arc;
 
 pub type Severity = libc::size_t;
 pub const SEVERITY_HELP: Severity = 0;
@@ -20,6 +24,18 @@ pub type CharStyle = libc::size_t;
 pub const CHAR_STYLE_FANCY: CharStyle = 0;
 pub const CHAR_STYLE_ASCII: CharStyle = 1;
 
+pub type OutputFormat = libc::size_t;
+pub const OUTPUT_FORMAT_TERM: OutputFormat = 0;
+pub const OUTPUT_FORMAT_GITHUB_ACTIONS: OutputFormat = 1;
+pub const OUTPUT_FORMAT_RUSTC_JSON: OutputFormat = 2;
+pub const OUTPUT_FORMAT_GNU: OutputFormat = 3;
+pub const OUTPUT_FORMAT_MSVC: OutputFormat = 4;
+
+pub type FormatOptions = libc::size_t;
+pub const FORMAT_OPTION_GITHUB_GROUP: FormatOptions = 1 << 0;
+pub const FORMAT_OPTION_RANGES: FormatOptions = 1 << 1;
+pub const FORMAT_OPTION_SECONDARY_NOTES: FormatOptions = 1 << 2;
+
 pub type WriterCallback = unsafe extern "C" fn(
     user_data: *mut libc::c_void,
     utf8_output: *const u8,
@@ -27,10 +43,16 @@ pub type WriterCallback = unsafe extern "C" fn(
 );
 
 #[repr(C)]
+#[derive(Clone)]
 pub struct CodespanDiagnostic {
-    diagnostic: csr_diag::Diagnostic<FileId>,
-    config: csr::term::Config,
-    writer: WriterCallback,
+    pub(crate) diagnostic: csr_diag::Diagnostic<FileId>,
+    pub(crate) config: csr::term::Config,
+    pub(crate) styles: csr::term::Styles,
+    pub(crate) writer: WriterCallback,
+    pub(crate) format: OutputFormat,
+    pub(crate) format_options: FormatOptions,
+    pub(crate) snapshot: Option<Arc<CodespanSourceMap>>,
+    pub(crate) sources: Option<SourceRecord>,
 }
 
 impl CodespanDiagnostic {
@@ -41,8 +63,8 @@ impl CodespanDiagnostic {
         message_length: libc::size_t,
         writer: Option<WriterCallback>,
     ) -> *mut Self {
-        let diagnostic = Box::new(Self {
-            diagnostic: csr_diag::Diagnostic::<FileId>::new(match severity {
+        let diagnostic = Box::new(Self::new(
+            csr_diag::Diagnostic::<FileId>::new(match severity {
                 SEVERITY_HELP => csr_diag::Severity::Help,
                 SEVERITY_NOTE => csr_diag::Severity::Note,
                 SEVERITY_WARNING => csr_diag::Severity::Warning,
@@ -51,9 +73,8 @@ impl CodespanDiagnostic {
                 _ => csr_diag::Severity::Error,
             })
             .with_message(Self::utf8_to_string(message, message_length)),
-            config: csr::term::Config::default(),
-            writer: writer.unwrap(),
-        });
+            writer.unwrap(),
+        ));
 
         Box::into_raw(diagnostic)
     }
@@ -70,28 +91,15 @@ impl CodespanDiagnostic {
         user_data: *mut libc::c_void,
         diagnostic: *const Self,
         source_map: *const CodespanSourceMap,
-        color: u8,
+        color: ColorMode,
     ) {
-        if !diagnostic.is_null() && !source_map.is_null() {
+        if !diagnostic.is_null() {
             let diagnostic = unsafe { &*diagnostic };
-            let source_map = unsafe { &*source_map };
-
-            let mut buffer_stream = if color == 0 {
-                termcolor::Buffer::no_color()
-            } else {
-                termcolor::Buffer::ansi()
-            };
-
-            csr::term::emit_to_write_style(
-                &mut buffer_stream,
-                &diagnostic.config,
-                source_map,
-                &diagnostic.diagnostic,
-            )
-            .unwrap();
-
-            let utf8_output = buffer_stream.into_inner();
-            unsafe { (diagnostic.writer)(user_data, utf8_output.as_ptr(), utf8_output.len()) };
+            if let Some(source_map) = unsafe { diagnostic.source_map(source_map) } {
+                // Nothing is written if a file cannot be read. The stream, fd
+                // and file writers report that as a status.
+                let _ = unsafe { diagnostic.write(user_data, source_map, color) };
+            }
         }
     }
 
@@ -164,22 +172,198 @@ impl CodespanDiagnostic {
     ) {
         if !diagnostic.is_null() {
             let diagnostic = unsafe { &mut (*diagnostic) };
-            diagnostic.config.display_style = match style {
-                DISPLAY_STYLE_SHORT => csr::term::DisplayStyle::Short,
-                DISPLAY_STYLE_MEDIUM => csr::term::DisplayStyle::Medium,
-                DISPLAY_STYLE_RICH => csr::term::DisplayStyle::Rich,
-                _ => csr::term::DisplayStyle::Rich,
-            };
-            diagnostic.config.chars = match chars {
-                CHAR_STYLE_ASCII => csr::term::Chars::ascii(),
-                CHAR_STYLE_FANCY => csr::term::Chars::default(),
-                _ => csr::term::Chars::ascii(),
+            Self::apply_config(&mut diagnostic.config, style, chars, tab_width);
+        }
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_diagnostic_apply_config(
+        diagnostic: *mut Self,
+        config: *const CodespanConfig,
+    ) {
+        if !diagnostic.is_null() && !config.is_null() {
+            let diagnostic = unsafe { &mut (*diagnostic) };
+            let config = unsafe { &*config };
+            diagnostic.config = config.config.clone();
+            diagnostic.styles = config.styles.clone();
+        }
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_diagnostic_set_format(
+        diagnostic: *mut Self,
+        format: OutputFormat,
+        options: FormatOptions,
+    ) {
+        if !diagnostic.is_null() {
+            let diagnostic = unsafe { &mut (*diagnostic) };
+            diagnostic.format = Self::output_format(format);
+            diagnostic.format_options = options;
+        }
+    }
+
+    pub(crate) fn output_format(format: OutputFormat) -> OutputFormat {
+        match format {
+            OUTPUT_FORMAT_GITHUB_ACTIONS => OUTPUT_FORMAT_GITHUB_ACTIONS,
+            OUTPUT_FORMAT_RUSTC_JSON => OUTPUT_FORMAT_RUSTC_JSON,
+            OUTPUT_FORMAT_GNU => OUTPUT_FORMAT_GNU,
+            OUTPUT_FORMAT_MSVC => OUTPUT_FORMAT_MSVC,
+            _ => OUTPUT_FORMAT_TERM,
+        }
+    }
+
+    pub(crate) fn apply_config(
+        config: &mut csr::term::Config,
+        style: DisplayStyle,
+        chars: CharStyle,
+        tab_width: libc::size_t,
+    ) {
+        config.display_style = match style {
+            DISPLAY_STYLE_SHORT => csr::term::DisplayStyle::Short,
+            DISPLAY_STYLE_MEDIUM => csr::term::DisplayStyle::Medium,
+            DISPLAY_STYLE_RICH => csr::term::DisplayStyle::Rich,
+            _ => csr::term::DisplayStyle::Rich,
+        };
+        config.chars = match chars {
+            CHAR_STYLE_ASCII => csr::term::Chars::ascii(),
+            CHAR_STYLE_FANCY => csr::term::Chars::default(),
+            _ => csr::term::Chars::ascii(),
+        };
+        config.tab_width = tab_width;
+    }
+
+    pub(crate) fn new(diagnostic: csr_diag::Diagnostic<FileId>, writer: WriterCallback) -> Self {
+        Self {
+            diagnostic,
+            config: csr::term::Config::default(),
+            styles: csr::term::Styles::default(),
+            writer,
+            format: OUTPUT_FORMAT_TERM,
+            format_options: 0,
+            snapshot: None,
+            sources: None,
+        }
+    }
+
+    // A snapshot taken with `codespan_diagnostic_snapshot` takes precedence
+    // over the given source map, which is only used without a snapshot and
+    // may then not be null.
+    pub(crate) unsafe fn source_map(
+        &self,
+        source_map: *const CodespanSourceMap,
+    ) -> Option<&CodespanSourceMap> {
+        self.snapshot.as_deref().or(unsafe { source_map.as_ref() })
+    }
+
+    pub(crate) unsafe fn write(
+        &self,
+        user_data: *mut libc::c_void,
+        source_map: &CodespanSourceMap,
+        color: ColorMode,
+    ) -> Result<(), csr::files::Error> {
+        let utf8_output = self.render_to_vec(source_map, color, None)?;
+        unsafe { (self.writer)(user_data, utf8_output.as_ptr(), utf8_output.len()) };
+        Ok(())
+    }
+
+    // `fd` is the file descriptor the output is written to, if any, which
+    // `CODESPAN_COLOR_AUTO` checks for a terminal.
+    pub(crate) fn render_to_vec(
+        &self,
+        source_map: &CodespanSourceMap,
+        color: ColorMode,
+        fd: Option<libc::c_int>,
+    ) -> Result<Vec<u8>, csr::files::Error> {
+        let mut buffer_stream = if color::enabled(color, fd) {
+            termcolor::Buffer::ansi()
+        } else {
+            termcolor::Buffer::no_color()
+        };
+
+        self.render(&mut buffer_stream, source_map)?;
+        Ok(buffer_stream.into_inner())
+    }
+
+    pub(crate) fn render<W: termcolor::WriteColor>(
+        &self,
+        writer: &mut W,
+        source_map: &CodespanSourceMap,
+    ) -> Result<(), csr::files::Error> {
+        // A snapshotted diagnostic is always rendered from its snapshot.
+        let source_map = self.snapshot.as_deref().unwrap_or(source_map);
+        match self.format {
+            OUTPUT_FORMAT_GITHUB_ACTIONS
+            | OUTPUT_FORMAT_RUSTC_JSON
+            | OUTPUT_FORMAT_GNU
+            | OUTPUT_FORMAT_MSVC => {}
+            _ => return self.render_term(writer, source_map),
+        }
+
+        // Files that are unavailable still leave the locations that are known.
+        let unavailable = output::plain::unavailable_sources(self, source_map);
+        if unavailable.is_empty() {
+            return match self.format {
+                OUTPUT_FORMAT_GITHUB_ACTIONS => output::github::emit(writer, self, source_map),
+                OUTPUT_FORMAT_RUSTC_JSON => output::rustc::emit(writer, self, source_map),
+                _ => output::line::emit(writer, self, source_map),
             };
-            diagnostic.config.tab_width = tab_width;
         }
+        let loci = output::plain::loci(self, source_map);
+        match self.format {
+            OUTPUT_FORMAT_GITHUB_ACTIONS => Ok(output::github::emit_unavailable(
+                writer,
+                self,
+                &loci,
+                &unavailable,
+            )?),
+            OUTPUT_FORMAT_RUSTC_JSON => Ok(output::rustc::emit_unavailable(
+                writer,
+                self,
+                &loci,
+                &unavailable,
+            )?),
+            _ => output::line::emit_unavailable(writer, self, &loci),
+        }
+    }
+
+    // Renders the terminal format whatever format the diagnostic is set to,
+    // falling back like `render` for unavailable and stale sources.
+    pub(crate) fn render_term<W: termcolor::WriteColor>(
+        &self,
+        writer: &mut W,
+        source_map: &CodespanSourceMap,
+    ) -> Result<(), csr::files::Error> {
+        let source_map = self.snapshot.as_deref().unwrap_or(source_map);
+        let unavailable = output::plain::unavailable_sources(self, source_map);
+        if !unavailable.is_empty() {
+            let loci = output::plain::loci(self, source_map);
+            return Ok(output::plain::emit_unavailable(
+                writer,
+                self,
+                &loci,
+                &unavailable,
+            )?);
+        }
+        match &self.sources {
+            Some(sources) if sources.is_stale(source_map) => sources.emit(writer, self, source_map),
+            _ => self.emit_term(writer, source_map),
+        }
+    }
+
+    pub(crate) fn emit_term<W: termcolor::WriteColor>(
+        &self,
+        writer: &mut W,
+        source_map: &CodespanSourceMap,
+    ) -> Result<(), csr::files::Error> {
+        csr::term::emit_to_write_style(
+            &mut csr::term::StylesWriter::new(writer, &self.styles),
+            &self.config,
+            source_map,
+            &self.diagnostic,
+        )
     }
 
-    fn utf8_to_string(utf8_data: *const u8, data_length: libc::size_t) -> String {
+    pub(crate) fn utf8_to_string(utf8_data: *const u8, data_length: libc::size_t) -> String {
         if utf8_data.is_null() {
             String::new()
         } else {
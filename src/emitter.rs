@@ -0,0 +1,691 @@
+use crate::baseline::{self, BaselineEntry, CodespanBaseline};
+use crate::color::ColorMode;
+use crate::dedup::{
+    DedupMode, Deduplicator, DEDUP_MODE_EXACT, DEDUP_MODE_NONE, DEDUP_MODE_PRIMARY_SPAN,
+};
+use crate::diagnostic::{CodespanDiagnostic, WriterCallback};
+use crate::output;
+use crate::sink::{CodespanSink, SinkOutput};
+use crate::source_map::CodespanSourceMap;
+use crate::status::{self, Status, STATUS_FILTERED, STATUS_INVALID_ARGUMENT, STATUS_OK};
+use crate::suppression::{self, CodespanSuppressionSyntax, Suppression, SuppressionSyntax};
+use crate::FileId;
+use codespan_reporting::diagnostic as csr_diag;
+use codespan_reporting::files::Files;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::mem;
+use std::sync::{Arc, Mutex, RwLock};
+
+pub type BaselineMode = libc::size_t;
+pub const BASELINE_MODE_SUPPRESS: BaselineMode = 0;
+pub const BASELINE_MODE_DOWNGRADE: BaselineMode = 1;
+
+pub type JobOrder = libc::size_t;
+pub const JOB_ORDER_INDEX: JobOrder = 0;
+pub const JOB_ORDER_COMMIT: JobOrder = 1;
+
+pub struct CodespanEmitter {
+    source_map: *const CodespanSourceMap,
+    state: Mutex<EmitterState>,
+    sinks: RwLock<Vec<Arc<CodespanSink>>>,
+    jobs: Mutex<JobQueue>,
+}
+
+// Everything mutable lives behind the locks, and the source map is only
+// read through callbacks that have to be thread-safe, see `CodespanSourceMap`.
+unsafe impl Send for CodespanEmitter {}
+unsafe impl Sync for CodespanEmitter {}
+
+struct EmitterState {
+    baseline: *mut CodespanBaseline,
+    baseline_mode: BaselineMode,
+    emitted: Vec<BaselineEntry>,
+    default_suppression_syntax: Option<SuppressionSyntax>,
+    suppression_syntaxes: HashMap<FileId, SuppressionSyntax>,
+    suppressions: HashMap<FileId, Vec<Suppression>>,
+    dedup_mode: DedupMode,
+    deduplicator: Deduplicator,
+}
+
+// A single rendered diagnostic is written with one call to its writer so
+// that output from different diagnostics never interleaves.
+enum Chunk {
+    Write {
+        user_data: *mut libc::c_void,
+        writer: WriterCallback,
+        utf8_output: Vec<u8>,
+    },
+    Sink {
+        index: usize,
+        output: SinkOutput,
+    },
+}
+
+// How `EmitterState::check` decided to emit a diagnostic.
+struct Verdict<'a> {
+    diagnostic: Cow<'a, CodespanDiagnostic>,
+    entry: BaselineEntry,
+    baselined: bool,
+}
+
+// What `EmitterState::commit` found when it rechecked a rendered diagnostic.
+// A retry renders it again, e.g. as an error instead of a downgraded note.
+enum Commit {
+    Emitted,
+    Duplicate,
+    Retry,
+}
+
+struct JobQueue {
+    order: JobOrder,
+    next_index: usize,
+    committed: BTreeMap<usize, Vec<Chunk>>,
+    // Diagnostics collected for each structured sink, in emission order.
+    buffered: Vec<Vec<CodespanDiagnostic>>,
+    // Output waiting to be written, and whether a thread is writing it.
+    ready: Vec<Chunk>,
+    writing: bool,
+    finished: bool,
+}
+
+pub struct CodespanJob {
+    emitter: *const CodespanEmitter,
+    index: usize,
+    chunks: Vec<Chunk>,
+}
+
+impl CodespanEmitter {
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_new_emitter(
+        source_map: *const CodespanSourceMap,
+    ) -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            source_map,
+            state: Mutex::new(EmitterState {
+                baseline: std::ptr::null_mut(),
+                baseline_mode: BASELINE_MODE_SUPPRESS,
+                emitted: Vec::new(),
+                default_suppression_syntax: None,
+                suppression_syntaxes: HashMap::new(),
+                suppressions: HashMap::new(),
+                dedup_mode: DEDUP_MODE_NONE,
+                deduplicator: Deduplicator::default(),
+            }),
+            sinks: RwLock::new(Vec::new()),
+            jobs: Mutex::new(JobQueue {
+                order: JOB_ORDER_INDEX,
+                next_index: 0,
+                committed: BTreeMap::new(),
+                buffered: Vec::new(),
+                ready: Vec::new(),
+                writing: false,
+                finished: false,
+            }),
+        }))
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_delete_emitter(emitter: *mut Self) {
+        if !emitter.is_null() {
+            unsafe { drop(Box::from_raw(emitter)) }
+        }
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_emitter_set_baseline(
+        emitter: *const Self,
+        baseline: *mut CodespanBaseline,
+        mode: BaselineMode,
+    ) {
+        if !emitter.is_null() {
+            let mut state = unsafe { &*emitter }.state.lock().unwrap();
+            state.baseline = baseline;
+            state.baseline_mode = match mode {
+                BASELINE_MODE_DOWNGRADE => BASELINE_MODE_DOWNGRADE,
+                _ => BASELINE_MODE_SUPPRESS,
+            };
+        }
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_emitter_set_dedup_mode(
+        emitter: *const Self,
+        mode: DedupMode,
+    ) {
+        if !emitter.is_null() {
+            let mut state = unsafe { &*emitter }.state.lock().unwrap();
+            state.dedup_mode = match mode {
+                DEDUP_MODE_EXACT => DEDUP_MODE_EXACT,
+                DEDUP_MODE_PRIMARY_SPAN => DEDUP_MODE_PRIMARY_SPAN,
+                _ => DEDUP_MODE_NONE,
+            };
+        }
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_emitter_deduplicated_count(
+        emitter: *const Self,
+    ) -> libc::size_t {
+        if emitter.is_null() {
+            0
+        } else {
+            unsafe { &*emitter }
+                .state
+                .lock()
+                .unwrap()
+                .deduplicator
+                .count
+        }
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_emitter_emit(
+        user_data: *mut libc::c_void,
+        emitter: *const Self,
+        diagnostic: *const CodespanDiagnostic,
+        color: ColorMode,
+    ) -> Status {
+        if emitter.is_null() || diagnostic.is_null() {
+            return STATUS_INVALID_ARGUMENT;
+        }
+
+        let emitter = unsafe { &*emitter };
+        let (chunks, status) = unsafe { emitter.render(user_data, &*diagnostic, color) };
+        unsafe { emitter.write(chunks) };
+        status
+    }
+
+    // Takes ownership of the sink. Once a sink is attached, diagnostics are
+    // only written to sinks, not to their own writer.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_emitter_add_sink(
+        emitter: *const Self,
+        sink: *mut CodespanSink,
+    ) {
+        if !emitter.is_null() && !sink.is_null() {
+            let emitter = unsafe { &*emitter };
+            emitter
+                .sinks
+                .write()
+                .unwrap()
+                .push(Arc::from(unsafe { Box::from_raw(sink) }));
+            emitter.jobs.lock().unwrap().buffered.push(Vec::new());
+        }
+    }
+
+    // Writes any jobs still waiting to be flushed, then the reports of all
+    // structured sinks. Only the first call writes anything.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_emitter_finish(emitter: *const Self) {
+        if emitter.is_null() {
+            return;
+        }
+
+        let emitter = unsafe { &*emitter };
+        let Some(source_map) = (unsafe { emitter.source_map.as_ref() }) else {
+            return;
+        };
+        let buffered: Vec<_> = {
+            let mut jobs = emitter.jobs.lock().unwrap();
+            if mem::replace(&mut jobs.finished, true) {
+                return;
+            }
+            jobs.flush();
+            jobs.buffered.iter_mut().map(mem::take).collect()
+        };
+        unsafe { emitter.drain() };
+        let sinks = emitter.sinks.read().unwrap().clone();
+        for (sink, buffered) in sinks.iter().zip(&buffered) {
+            unsafe { sink.finish(buffered, source_map) };
+        }
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_emitter_set_job_order(emitter: *const Self, order: JobOrder) {
+        if !emitter.is_null() {
+            let mut jobs = unsafe { &*emitter }.jobs.lock().unwrap();
+            jobs.order = match order {
+                JOB_ORDER_COMMIT => JOB_ORDER_COMMIT,
+                _ => JOB_ORDER_INDEX,
+            };
+        }
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_emitter_begin_job(
+        emitter: *const Self,
+        job_index: libc::size_t,
+    ) -> *mut CodespanJob {
+        if emitter.is_null() {
+            return std::ptr::null_mut();
+        }
+
+        Box::into_raw(Box::new(CodespanJob {
+            emitter,
+            index: job_index,
+            chunks: Vec::new(),
+        }))
+    }
+
+    // Writes jobs that are still waiting on an earlier job index, e.g.
+    // because that job was never started.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_emitter_flush(emitter: *const Self) {
+        if !emitter.is_null() {
+            unsafe { (*emitter).flush() };
+        }
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_emitter_write_baseline(
+        user_data: *mut libc::c_void,
+        emitter: *const Self,
+        writer: Option<WriterCallback>,
+    ) -> Status {
+        let (false, Some(writer)) = (emitter.is_null(), writer) else {
+            return STATUS_INVALID_ARGUMENT;
+        };
+        let emitter = unsafe { &*emitter };
+        let utf8_output =
+            baseline::write_entries(emitter.state.lock().unwrap().emitted.iter()).into_bytes();
+        unsafe {
+            emitter.write(vec![Chunk::Write {
+                user_data,
+                writer,
+                utf8_output,
+            }])
+        };
+        STATUS_OK
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_emitter_set_default_suppression_syntax(
+        emitter: *const Self,
+        syntax: *const CodespanSuppressionSyntax,
+    ) {
+        if !emitter.is_null() {
+            let mut state = unsafe { &*emitter }.state.lock().unwrap();
+            state.default_suppression_syntax = unsafe { SuppressionSyntax::from_raw(syntax) };
+            state.suppressions.clear();
+        }
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_emitter_set_suppression_syntax(
+        emitter: *const Self,
+        file_id: FileId,
+        syntax: *const CodespanSuppressionSyntax,
+    ) {
+        if !emitter.is_null() {
+            let mut state = unsafe { &*emitter }.state.lock().unwrap();
+            match unsafe { SuppressionSyntax::from_raw(syntax) } {
+                Some(syntax) => state.suppression_syntaxes.insert(file_id, syntax),
+                None => state.suppression_syntaxes.remove(&file_id),
+            };
+            state.suppressions.remove(&file_id);
+        }
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_emitter_scan_suppressions(
+        emitter: *const Self,
+        file_id: FileId,
+    ) {
+        if !emitter.is_null() {
+            let emitter = unsafe { &*emitter };
+            if let Some(source_map) = unsafe { emitter.source_map.as_ref() } {
+                emitter
+                    .state
+                    .lock()
+                    .unwrap()
+                    .scan_suppressions(file_id, source_map);
+            }
+        }
+    }
+
+    // Stops at the first warning that cannot be rendered and returns its
+    // status; the warnings before it are still written.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_emitter_write_unused_suppressions(
+        user_data: *mut libc::c_void,
+        emitter: *const Self,
+        color: ColorMode,
+        writer: Option<WriterCallback>,
+    ) -> Status {
+        let (false, Some(writer)) = (emitter.is_null(), writer) else {
+            return STATUS_INVALID_ARGUMENT;
+        };
+        let emitter = unsafe { &*emitter };
+        let Some(source_map) = (unsafe { emitter.source_map.as_ref() }) else {
+            return STATUS_INVALID_ARGUMENT;
+        };
+
+        let unused: Vec<_> = {
+            let state = emitter.state.lock().unwrap();
+            let mut file_ids: Vec<&FileId> = state.suppressions.keys().collect();
+            file_ids.sort();
+            file_ids
+                .into_iter()
+                .flat_map(|file_id| {
+                    suppression::unused_diagnostics(*file_id, &state.suppressions[file_id])
+                })
+                .collect()
+        };
+
+        let mut chunks = Vec::new();
+        let mut status = STATUS_OK;
+        for unused in unused {
+            match CodespanDiagnostic::new(unused, writer).render_to_vec(source_map, color, None) {
+                Ok(utf8_output) => chunks.push(Chunk::Write {
+                    user_data,
+                    writer,
+                    utf8_output,
+                }),
+                Err(error) => {
+                    status = status::from_error(&error);
+                    break;
+                }
+            }
+        }
+        unsafe { emitter.write(chunks) };
+        status
+    }
+
+    // Applies the emitter's filters and renders the diagnostic on the calling
+    // thread. The filters only record the diagnostic once it was rendered for
+    // the writer or at least one sink, so a diagnostic that failed to render
+    // can be emitted again. Returns the output and the status of the first
+    // output that failed, or STATUS_FILTERED.
+    unsafe fn render(
+        &self,
+        user_data: *mut libc::c_void,
+        diagnostic: &CodespanDiagnostic,
+        color: ColorMode,
+    ) -> (Vec<Chunk>, Status) {
+        let Some(source_map) = (unsafe { self.source_map.as_ref() }) else {
+            return (Vec::new(), STATUS_INVALID_ARGUMENT);
+        };
+        loop {
+            let Some(verdict) = self.state.lock().unwrap().check(diagnostic, source_map) else {
+                return (Vec::new(), STATUS_FILTERED);
+            };
+            let (chunks, status) =
+                self.render_outputs(user_data, &verdict.diagnostic, source_map, color);
+            if chunks.is_empty() {
+                return (chunks, status);
+            }
+            match self.state.lock().unwrap().commit(diagnostic, verdict) {
+                Commit::Emitted => return (chunks, status),
+                Commit::Duplicate => return (Vec::new(), STATUS_FILTERED),
+                Commit::Retry => {}
+            }
+        }
+    }
+
+    // Each sink renders on its own, so a sink that fails does not keep the
+    // diagnostic from the others.
+    fn render_outputs(
+        &self,
+        user_data: *mut libc::c_void,
+        diagnostic: &CodespanDiagnostic,
+        source_map: &CodespanSourceMap,
+        color: ColorMode,
+    ) -> (Vec<Chunk>, Status) {
+        let sinks = self.sinks.read().unwrap();
+        if sinks.is_empty() {
+            return match diagnostic.render_to_vec(source_map, color, None) {
+                Ok(utf8_output) => (
+                    vec![Chunk::Write {
+                        user_data,
+                        writer: diagnostic.writer,
+                        utf8_output,
+                    }],
+                    STATUS_OK,
+                ),
+                Err(error) => (Vec::new(), status::from_error(&error)),
+            };
+        }
+
+        let mut chunks = Vec::new();
+        let mut status = STATUS_OK;
+        for (index, sink) in sinks.iter().enumerate() {
+            match sink.render(diagnostic, source_map) {
+                Ok(output) => chunks.push(Chunk::Sink { index, output }),
+                Err(error) if status == STATUS_OK => status = status::from_error(&error),
+                Err(_) => {}
+            }
+        }
+        (chunks, status)
+    }
+
+    unsafe fn write(&self, chunks: Vec<Chunk>) {
+        self.jobs.lock().unwrap().queue(chunks);
+        unsafe { self.drain() };
+    }
+
+    unsafe fn flush(&self) {
+        self.jobs.lock().unwrap().flush();
+        unsafe { self.drain() };
+    }
+
+    // Writers are called without holding any lock so that they can call back
+    // into the emitter. Only one thread writes at a time, which keeps the
+    // output in order; anything queued meanwhile is written by that thread.
+    unsafe fn drain(&self) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs.writing {
+            return;
+        }
+        jobs.writing = true;
+        loop {
+            let chunks = mem::take(&mut jobs.ready);
+            if chunks.is_empty() {
+                jobs.writing = false;
+                return;
+            }
+            drop(jobs);
+
+            let sinks = self.sinks.read().unwrap().clone();
+            for chunk in chunks {
+                match chunk {
+                    Chunk::Write {
+                        user_data,
+                        writer,
+                        utf8_output,
+                    } => unsafe { writer(user_data, utf8_output.as_ptr(), utf8_output.len()) },
+                    Chunk::Sink {
+                        index,
+                        output: SinkOutput::Write(utf8_output),
+                    } => unsafe { sinks[index].write(&utf8_output) },
+                    Chunk::Sink {
+                        output: SinkOutput::Buffer(_),
+                        ..
+                    } => unreachable!(),
+                }
+            }
+            jobs = self.jobs.lock().unwrap();
+        }
+    }
+}
+
+// Jobs that were committed but never flushed are still written.
+impl Drop for CodespanEmitter {
+    fn drop(&mut self) {
+        unsafe { self.flush() };
+    }
+}
+
+impl EmitterState {
+    // Decides how a diagnostic is emitted without recording it. Returns None
+    // if it is filtered.
+    fn check<'a>(
+        &mut self,
+        diagnostic: &'a CodespanDiagnostic,
+        source_map: &CodespanSourceMap,
+    ) -> Option<Verdict<'a>> {
+        if self.is_suppressed(&diagnostic.diagnostic, source_map) {
+            return None;
+        }
+        if self
+            .deduplicator
+            .is_duplicate(self.dedup_mode, &diagnostic.diagnostic)
+        {
+            self.deduplicator.count += 1;
+            return None;
+        }
+
+        let entry = BaselineEntry::new(diagnostic, source_map);
+        let baselined =
+            !self.baseline.is_null() && unsafe { &*self.baseline }.contains(entry.fingerprint);
+        match (baselined, self.baseline_mode) {
+            (false, _) => Some(Verdict {
+                diagnostic: Cow::Borrowed(diagnostic),
+                entry,
+                baselined,
+            }),
+            (true, BASELINE_MODE_DOWNGRADE) => {
+                let mut downgraded = diagnostic.clone();
+                downgraded.diagnostic.severity =
+                    downgraded.diagnostic.severity.min(csr_diag::Severity::Note);
+                Some(Verdict {
+                    diagnostic: Cow::Owned(downgraded),
+                    entry,
+                    baselined,
+                })
+            }
+            // Nothing is rendered, so the diagnostic is recorded right away.
+            (true, _) => {
+                let verdict = Verdict {
+                    diagnostic: Cow::Borrowed(diagnostic),
+                    entry,
+                    baselined,
+                };
+                self.commit(diagnostic, verdict);
+                None
+            }
+        }
+    }
+
+    // Records a rendered diagnostic. Another thread may have emitted a
+    // duplicate or used up the baseline entry since `check`.
+    fn commit(&mut self, diagnostic: &CodespanDiagnostic, verdict: Verdict) -> Commit {
+        if self
+            .deduplicator
+            .is_duplicate(self.dedup_mode, &diagnostic.diagnostic)
+        {
+            self.deduplicator.count += 1;
+            return Commit::Duplicate;
+        }
+        if verdict.baselined
+            && (self.baseline.is_null()
+                || !unsafe { &mut *self.baseline }.take(verdict.entry.fingerprint))
+        {
+            return Commit::Retry;
+        }
+        self.deduplicator
+            .insert(self.dedup_mode, &diagnostic.diagnostic);
+        self.emitted.push(verdict.entry);
+        Commit::Emitted
+    }
+
+    fn scan_suppressions(
+        &mut self,
+        file_id: FileId,
+        source_map: &CodespanSourceMap,
+    ) -> Option<&mut Vec<Suppression>> {
+        if !self.suppressions.contains_key(&file_id) {
+            let syntax = self
+                .suppression_syntaxes
+                .get(&file_id)
+                .or(self.default_suppression_syntax.as_ref())?;
+            let suppressions = suppression::scan(source_map, file_id, syntax).ok()?;
+            self.suppressions.insert(file_id, suppressions);
+        }
+        self.suppressions.get_mut(&file_id)
+    }
+
+    fn is_suppressed(
+        &mut self,
+        diagnostic: &csr_diag::Diagnostic<FileId>,
+        source_map: &CodespanSourceMap,
+    ) -> bool {
+        let Some(label) = output::primary_label(diagnostic) else {
+            return false;
+        };
+        let Ok(line_index) = source_map.line_index(label.file_id, label.range.start) else {
+            return false;
+        };
+        self.scan_suppressions(label.file_id, source_map)
+            .is_some_and(|suppressions| {
+                suppression::suppress(suppressions, line_index, diagnostic.code.as_deref())
+            })
+    }
+}
+
+impl JobQueue {
+    fn queue(&mut self, chunks: Vec<Chunk>) {
+        for chunk in chunks {
+            match chunk {
+                Chunk::Sink {
+                    index,
+                    output: SinkOutput::Buffer(diagnostic),
+                } => self.buffered[index].push(*diagnostic),
+                chunk => self.ready.push(chunk),
+            }
+        }
+    }
+
+    fn commit(&mut self, index: usize, chunks: Vec<Chunk>) {
+        // Jobs committed in commit order, or after their slot has already
+        // been flushed, are written straight away.
+        if self.order == JOB_ORDER_COMMIT || index < self.next_index {
+            self.queue(chunks);
+            return;
+        }
+
+        self.committed.entry(index).or_default().extend(chunks);
+        while let Some(chunks) = self.committed.remove(&self.next_index) {
+            self.queue(chunks);
+            self.next_index += 1;
+        }
+    }
+
+    fn flush(&mut self) {
+        for (index, chunks) in mem::take(&mut self.committed) {
+            self.queue(chunks);
+            self.next_index = index + 1;
+        }
+    }
+}
+
+impl CodespanJob {
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_job_emit(
+        user_data: *mut libc::c_void,
+        job: *mut Self,
+        diagnostic: *const CodespanDiagnostic,
+        color: ColorMode,
+    ) -> Status {
+        if job.is_null() || diagnostic.is_null() {
+            return STATUS_INVALID_ARGUMENT;
+        }
+
+        let job = unsafe { &mut *job };
+        let emitter = unsafe { &*job.emitter };
+        let (chunks, status) = unsafe { emitter.render(user_data, &*diagnostic, color) };
+        job.chunks.extend(chunks);
+        status
+    }
+
+    // Hands the job's output to its emitter and frees the job.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_job_commit(job: *mut Self) {
+        if !job.is_null() {
+            let job = unsafe { Box::from_raw(job) };
+            let emitter = unsafe { &*job.emitter };
+            emitter.jobs.lock().unwrap().commit(job.index, job.chunks);
+            unsafe { emitter.drain() };
+        }
+    }
+}
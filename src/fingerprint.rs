@@ -0,0 +1,89 @@
+use crate::diagnostic::CodespanDiagnostic;
+use crate::output;
+use crate::source_map::CodespanSourceMap;
+use crate::FileId;
+use codespan_reporting::diagnostic as csr_diag;
+use codespan_reporting::files::Files;
+
+pub type Fingerprint = u64;
+
+impl CodespanDiagnostic {
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_diagnostic_fingerprint(
+        diagnostic: *const Self,
+        source_map: *const CodespanSourceMap,
+    ) -> Fingerprint {
+        if diagnostic.is_null() || source_map.is_null() {
+            0
+        } else {
+            let diagnostic = unsafe { &*diagnostic };
+            let source_map = unsafe { &*source_map };
+            fingerprint(&diagnostic.diagnostic, source_map)
+        }
+    }
+}
+
+// The fingerprint deliberately ignores byte offsets and line numbers, which
+// change whenever unrelated code is added above the diagnostic, and hashes the
+// text of the labelled lines instead.
+pub(crate) fn fingerprint(
+    diagnostic: &csr_diag::Diagnostic<FileId>,
+    source_map: &CodespanSourceMap,
+) -> Fingerprint {
+    let mut hasher = Fnv1a::new();
+    hasher.write_field(diagnostic.code.as_deref().unwrap_or_default());
+    hasher.write_field(&diagnostic.message);
+
+    if let Some(label) = output::primary_label(diagnostic) {
+        hasher.write_field(source_map.name(label.file_id).unwrap_or_default());
+        hasher.write_field(&normalize(
+            &labelled_lines(source_map, label).unwrap_or_default(),
+        ));
+    }
+    hasher.finish()
+}
+
+pub(crate) fn labelled_lines(
+    source_map: &CodespanSourceMap,
+    label: &csr_diag::Label<FileId>,
+) -> Result<String, codespan_reporting::files::Error> {
+    let source = source_map.source(label.file_id)?;
+    let last_byte = label.range.end.max(label.range.start + 1) - 1;
+    let start = source_map.line_index(label.file_id, label.range.start)?;
+    let end = source_map.line_index(label.file_id, last_byte)?;
+
+    let mut lines = String::new();
+    for line_index in start..=end {
+        let line_range = source_map.line_range(label.file_id, line_index)?;
+        lines.push_str(source.get(line_range).unwrap_or_default());
+    }
+    Ok(lines)
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+pub(crate) struct Fnv1a(u64);
+
+impl Fnv1a {
+    pub(crate) fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    pub(crate) fn write_field(&mut self, field: &str) {
+        self.write(field.as_bytes());
+        self.write(&[0]);
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+}
@@ -1,19 +1,56 @@
 #![feature(bool_to_result, vec_into_raw_parts)]
+pub mod baseline;
+pub mod color;
+pub mod config;
+pub mod dedup;
 pub mod diagnostic;
+pub mod emitter;
+pub mod fingerprint;
+pub mod list;
+pub mod output;
+pub mod rebase;
+pub mod sink;
+pub mod snapshot;
 pub mod source_map;
+pub mod staleness;
+pub mod status;
+pub mod stream;
+pub mod suppression;
+pub mod writer;
 
 pub type FileId = libc::size_t;
 pub type ByteIndex = libc::size_t;
 
 #[cfg(test)]
 mod tests {
+    use std::cell::Cell;
     use std::{ptr, slice};
 
     use super::*;
+    use crate::baseline::CodespanBaseline;
+    use crate::color::{self, COLOR_ALWAYS, COLOR_AUTO, COLOR_NEVER};
+    use crate::config::CodespanConfig;
+    use crate::dedup::{DEDUP_MODE_EXACT, DEDUP_MODE_PRIMARY_SPAN};
     use crate::diagnostic::{
-        CodespanDiagnostic, CHAR_STYLE_FANCY, DISPLAY_STYLE_RICH, SEVERITY_ERROR,
+        CodespanDiagnostic, CHAR_STYLE_FANCY, DISPLAY_STYLE_RICH, DISPLAY_STYLE_SHORT,
+        FORMAT_OPTION_GITHUB_GROUP, FORMAT_OPTION_RANGES, FORMAT_OPTION_SECONDARY_NOTES,
+        OUTPUT_FORMAT_GITHUB_ACTIONS, OUTPUT_FORMAT_GNU, OUTPUT_FORMAT_MSVC,
+        OUTPUT_FORMAT_RUSTC_JSON, OUTPUT_FORMAT_TERM, SEVERITY_ERROR, SEVERITY_WARNING,
     };
-    use crate::source_map::{CodespanSourceMap, LineIndex};
+    use crate::emitter::{
+        CodespanEmitter, CodespanJob, BASELINE_MODE_DOWNGRADE, BASELINE_MODE_SUPPRESS,
+        JOB_ORDER_COMMIT,
+    };
+    use crate::list::{
+        CodespanDiagnosticList, LIST_OPTION_FILE_HEADERS, SORT_ORDER_POSITION, SORT_ORDER_SEVERITY,
+    };
+    use crate::output::sarif::CodespanToolInfo;
+    use crate::rebase::{REBASE_MODE_DROP, REBASE_MODE_FLAG};
+    use crate::sink::CodespanSink;
+    use crate::source_map::{CodespanSourceMap, CodespanTextEdit, LineIndex};
+    use crate::staleness::{STALE_MODE_NOTE, STALE_MODE_NO_SNIPPET};
+    use crate::status::{STATUS_FILE_MISSING, STATUS_FILTERED, STATUS_INVALID_ARGUMENT, STATUS_OK};
+    use crate::suppression::CodespanSuppressionSyntax;
     use codespan_reporting as csr;
     use codespan_reporting::diagnostic as csr_diag;
     use codespan_reporting::files::{Files, SimpleFiles};
@@ -266,4 +303,2416 @@ mod tests {
             CodespanSourceMap::codespan_delete_source_map(src_map)
         }
     }
+
+    unsafe extern "C" fn collect_callback(
+        user_data: *mut libc::c_void,
+        utf8_output: *const u8,
+        output_length: libc::size_t,
+    ) {
+        let output = unsafe { &mut *(user_data as *mut String) };
+        output.push_str(
+            str::from_utf8(unsafe { slice::from_raw_parts(utf8_output, output_length) }).unwrap(),
+        );
+    }
+
+    fn fizz_buzz_map() -> (SimpleMap, FileId) {
+        let mut simple_map = SimpleMap::new();
+        let file_id = simple_map.add(
+            "src/FizzBuzz.fun".to_owned(),
+            unindent(
+                r#"
+            module FizzBuzz where
+
+            fizz₁ : Nat → String
+            fizz₁ num = case (mod num 5) (mod num 3) of
+                0 0 => "FizzBuzz"
+                0 _ => "Fizz"
+                _ 0 => "Buzz"
+                _ _ => num
+            "#,
+            ),
+        );
+        (simple_map, file_id)
+    }
+
+    fn new_source_map(simple_map: &mut SimpleMap) -> *mut CodespanSourceMap {
+        unsafe {
+            CodespanSourceMap::codespan_new_source_map(
+                simple_map as *mut _ as *mut libc::c_void,
+                Some(file_name),
+                Some(source_code),
+                Some(line_index),
+                Some(line_range),
+                None,
+                None,
+            )
+        }
+    }
+
+    fn new_fizz_buzz_diagnostic(file_id: FileId) -> *mut CodespanDiagnostic {
+        let header_msg = "`case` clauses have incompatible types".as_bytes();
+        let primary_msg = "expected `String`, found `Nat`".as_bytes();
+        let secondary_msg = "this is found to be of type `String`".as_bytes();
+        let note = "expected type `String`".as_bytes();
+        let code = "E0308".as_bytes();
+        unsafe {
+            let diagnostic = CodespanDiagnostic::codespan_new_diagnostic(
+                SEVERITY_ERROR,
+                header_msg.as_ptr(),
+                header_msg.len(),
+                Some(collect_callback),
+            );
+            CodespanDiagnostic::codespan_diagnostic_set_code(diagnostic, code.as_ptr(), code.len());
+            CodespanDiagnostic::codespan_diagnostic_set_primary(
+                diagnostic,
+                file_id,
+                163,
+                166,
+                primary_msg.as_ptr(),
+                primary_msg.len(),
+            );
+            CodespanDiagnostic::codespan_diagnostic_add_secondary(
+                diagnostic,
+                file_id,
+                105,
+                115,
+                secondary_msg.as_ptr(),
+                secondary_msg.len(),
+            );
+            CodespanDiagnostic::codespan_diagnostic_add_note(diagnostic, note.as_ptr(), note.len());
+            diagnostic
+        }
+    }
+
+    #[test]
+    fn sarif_test() {
+        let (mut simple_map, file_id) = fizz_buzz_map();
+        let src_map = new_source_map(&mut simple_map);
+        let diagnostic = new_fizz_buzz_diagnostic(file_id);
+
+        let name = "funcc".as_bytes();
+        let version = "1.2.0".as_bytes();
+        let tool = CodespanToolInfo {
+            name: name.as_ptr(),
+            name_length: name.len(),
+            version: version.as_ptr(),
+            version_length: version.len(),
+            information_uri: ptr::null(),
+            information_uri_length: 0,
+        };
+
+        let mut output = String::new();
+        let diagnostics = [diagnostic as *const CodespanDiagnostic];
+        unsafe {
+            CodespanDiagnostic::codespan_write_sarif(
+                &mut output as *mut _ as *mut libc::c_void,
+                diagnostics.as_ptr(),
+                diagnostics.len(),
+                src_map,
+                &tool,
+                Some(collect_callback),
+            );
+        }
+
+        let log: serde_json::Value = serde_json::from_str(&output).unwrap();
+        let run = &log["runs"][0];
+        assert_eq!(run["tool"]["driver"]["name"], "funcc");
+        assert_eq!(run["tool"]["driver"]["version"], "1.2.0");
+        assert_eq!(run["artifacts"][0]["location"]["uri"], "src/FizzBuzz.fun");
+
+        let result = &run["results"][0];
+        assert_eq!(result["ruleId"], "E0308");
+        assert_eq!(result["level"], "error");
+        let region = &result["locations"][0]["physicalLocation"]["region"];
+        assert_eq!(region["startLine"], 8);
+        assert_eq!(region["startColumn"], 12);
+        assert_eq!(
+            result["relatedLocations"][0]["physicalLocation"]["region"]["startLine"],
+            5
+        );
+
+        unsafe {
+            CodespanDiagnostic::codespan_delete_diagnostic(diagnostic);
+            CodespanSourceMap::codespan_delete_source_map(src_map)
+        }
+    }
+
+    #[test]
+    fn lsp_test() {
+        let (mut simple_map, file_id) = fizz_buzz_map();
+        let src_map = new_source_map(&mut simple_map);
+        let diagnostic = new_fizz_buzz_diagnostic(file_id);
+
+        let binding_msg = "bound here".as_bytes();
+        let source = "funcc".as_bytes();
+        let mut output = String::new();
+        unsafe {
+            CodespanDiagnostic::codespan_diagnostic_add_secondary(
+                diagnostic,
+                file_id,
+                56,
+                59,
+                binding_msg.as_ptr(),
+                binding_msg.len(),
+            );
+            CodespanDiagnostic::codespan_write_lsp_diagnostics(
+                &mut output as *mut _ as *mut libc::c_void,
+                diagnostic,
+                src_map,
+                source.as_ptr(),
+                source.len(),
+                Some(collect_callback),
+            );
+        }
+
+        let params: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(params[0]["uri"], "src/FizzBuzz.fun");
+
+        let lsp_diagnostic = &params[0]["diagnostics"][0];
+        assert_eq!(lsp_diagnostic["severity"], 1);
+        assert_eq!(lsp_diagnostic["code"], "E0308");
+        assert_eq!(lsp_diagnostic["source"], "funcc");
+        assert_eq!(
+            lsp_diagnostic["message"],
+            "`case` clauses have incompatible types\nexpected type `String`"
+        );
+        assert_eq!(lsp_diagnostic["range"]["start"]["line"], 7);
+        assert_eq!(lsp_diagnostic["range"]["start"]["character"], 11);
+
+        let related = &lsp_diagnostic["relatedInformation"];
+        assert_eq!(related[0]["location"]["range"]["start"]["line"], 4);
+        assert_eq!(related[1]["location"]["range"]["start"]["line"], 3);
+        assert_eq!(related[1]["location"]["range"]["start"]["character"], 6);
+        assert_eq!(related[1]["location"]["range"]["end"]["character"], 9);
+
+        unsafe {
+            CodespanDiagnostic::codespan_delete_diagnostic(diagnostic);
+            CodespanSourceMap::codespan_delete_source_map(src_map)
+        }
+    }
+
+    #[test]
+    fn github_actions_test() {
+        let (mut simple_map, file_id) = fizz_buzz_map();
+        let src_map = new_source_map(&mut simple_map);
+        let diagnostic = new_fizz_buzz_diagnostic(file_id);
+
+        let mut output = String::new();
+        unsafe {
+            CodespanDiagnostic::codespan_diagnostic_set_format(
+                diagnostic,
+                OUTPUT_FORMAT_GITHUB_ACTIONS,
+                FORMAT_OPTION_GITHUB_GROUP,
+            );
+            CodespanDiagnostic::codespan_write_diagnostic(
+                &mut output as *mut _ as *mut libc::c_void,
+                diagnostic,
+                src_map,
+                0,
+            );
+        }
+
+        let mut lines = output.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "::error file=src/FizzBuzz.fun,line=8,col=12,endLine=8,endColumn=15,title=E0308::\
+             `case` clauses have incompatible types%0Aexpected type `String`"
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            "::group::`case` clauses have incompatible types"
+        );
+        assert!(output.contains("error[E0308]: `case` clauses have incompatible types\n"));
+        assert!(output.ends_with("::endgroup::\n"));
+
+        unsafe {
+            CodespanDiagnostic::codespan_delete_diagnostic(diagnostic);
+            CodespanSourceMap::codespan_delete_source_map(src_map)
+        }
+    }
+
+    #[test]
+    fn gitlab_code_quality_test() {
+        let (mut simple_map, file_id) = fizz_buzz_map();
+        let src_map = new_source_map(&mut simple_map);
+        let first = new_fizz_buzz_diagnostic(file_id);
+        let second = new_fizz_buzz_diagnostic(file_id);
+
+        let mut output = String::new();
+        let diagnostics = [first as *const CodespanDiagnostic, second];
+        unsafe {
+            CodespanDiagnostic::codespan_write_gitlab_code_quality(
+                &mut output as *mut _ as *mut libc::c_void,
+                diagnostics.as_ptr(),
+                diagnostics.len(),
+                src_map,
+                Some(collect_callback),
+            );
+        }
+
+        let report: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(report[0]["check_name"], "E0308");
+        assert_eq!(report[0]["severity"], "critical");
+        assert_eq!(report[0]["location"]["path"], "src/FizzBuzz.fun");
+        assert_eq!(report[0]["location"]["lines"]["begin"], 8);
+        assert_ne!(report[0]["fingerprint"], report[1]["fingerprint"]);
+
+        unsafe {
+            CodespanDiagnostic::codespan_delete_diagnostic(first);
+            CodespanDiagnostic::codespan_delete_diagnostic(second);
+            CodespanSourceMap::codespan_delete_source_map(src_map)
+        }
+    }
+
+    #[test]
+    fn checkstyle_and_junit_test() {
+        let (mut simple_map, file_id) = fizz_buzz_map();
+        let src_map = new_source_map(&mut simple_map);
+        let diagnostic = new_fizz_buzz_diagnostic(file_id);
+        let diagnostics = [diagnostic as *const CodespanDiagnostic];
+
+        let mut checkstyle = String::new();
+        let mut junit = String::new();
+        let suite_name = "funcc".as_bytes();
+        unsafe {
+            CodespanDiagnostic::codespan_diagnostic_set_format(
+                diagnostic,
+                OUTPUT_FORMAT_GITHUB_ACTIONS,
+                0,
+            );
+            CodespanDiagnostic::codespan_write_checkstyle(
+                &mut checkstyle as *mut _ as *mut libc::c_void,
+                diagnostics.as_ptr(),
+                diagnostics.len(),
+                src_map,
+                Some(collect_callback),
+            );
+            CodespanDiagnostic::codespan_write_junit(
+                &mut junit as *mut _ as *mut libc::c_void,
+                diagnostics.as_ptr(),
+                diagnostics.len(),
+                src_map,
+                suite_name.as_ptr(),
+                suite_name.len(),
+                Some(collect_callback),
+            );
+        }
+
+        assert!(checkstyle.contains("<file name=\"src/FizzBuzz.fun\">"));
+        assert!(checkstyle.contains(
+            "<error line=\"8\" column=\"12\" severity=\"error\" message=\"`case` clauses have \
+             incompatible types&#10;expected type `String`\" source=\"E0308\"/>"
+        ));
+
+        assert!(junit.contains("<testsuite name=\"funcc\" tests=\"1\" failures=\"1\">"));
+        assert!(junit.contains("<testcase name=\"src/FizzBuzz.fun\" classname=\"funcc\">"));
+        assert!(junit.contains(
+            "<failure message=\"`case` clauses have incompatible types\" type=\"E0308\">"
+        ));
+        assert!(junit.contains("_ _ =&gt; num"));
+        assert!(!junit.contains("::error"));
+
+        unsafe {
+            CodespanDiagnostic::codespan_delete_diagnostic(diagnostic);
+            CodespanSourceMap::codespan_delete_source_map(src_map)
+        }
+    }
+
+    #[test]
+    fn rustc_json_test() {
+        let (mut simple_map, file_id) = fizz_buzz_map();
+        let src_map = new_source_map(&mut simple_map);
+        let diagnostic = new_fizz_buzz_diagnostic(file_id);
+
+        let mut output = String::new();
+        unsafe {
+            CodespanDiagnostic::codespan_diagnostic_set_format(
+                diagnostic,
+                OUTPUT_FORMAT_RUSTC_JSON,
+                0,
+            );
+            CodespanDiagnostic::codespan_write_diagnostic(
+                &mut output as *mut _ as *mut libc::c_void,
+                diagnostic,
+                src_map,
+                0,
+            );
+        }
+
+        assert_eq!(output.lines().count(), 1);
+        let message: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(message["level"], "error");
+        assert_eq!(message["code"]["code"], "E0308");
+
+        let primary = &message["spans"][0];
+        assert_eq!(primary["file_name"], "src/FizzBuzz.fun");
+        assert_eq!(primary["byte_start"], 163);
+        assert_eq!(primary["line_start"], 8);
+        assert_eq!(primary["column_start"], 12);
+        assert_eq!(primary["is_primary"], true);
+        assert_eq!(primary["text"][0]["text"], "    _ _ => num");
+        assert_eq!(message["spans"][1]["is_primary"], false);
+
+        assert_eq!(message["children"][0]["level"], "note");
+        assert_eq!(message["children"][0]["message"], "expected type `String`");
+        assert!(message["rendered"]
+            .as_str()
+            .unwrap()
+            .starts_with("error[E0308]: `case` clauses have incompatible types\n"));
+
+        unsafe {
+            CodespanDiagnostic::codespan_delete_diagnostic(diagnostic);
+            CodespanSourceMap::codespan_delete_source_map(src_map)
+        }
+    }
+
+    #[test]
+    fn line_format_test() {
+        let (mut simple_map, file_id) = fizz_buzz_map();
+        let src_map = new_source_map(&mut simple_map);
+        let diagnostic = new_fizz_buzz_diagnostic(file_id);
+
+        let render = |format, options| {
+            let mut output = String::new();
+            unsafe {
+                CodespanDiagnostic::codespan_diagnostic_set_format(diagnostic, format, options);
+                CodespanDiagnostic::codespan_write_diagnostic(
+                    &mut output as *mut _ as *mut libc::c_void,
+                    diagnostic,
+                    src_map,
+                    0,
+                );
+            }
+            output
+        };
+
+        assert_eq!(
+            render(OUTPUT_FORMAT_GNU, 0),
+            "src/FizzBuzz.fun:8:12: error: `case` clauses have incompatible types [E0308]\n"
+        );
+        assert_eq!(
+            render(OUTPUT_FORMAT_GNU, FORMAT_OPTION_RANGES),
+            "src/FizzBuzz.fun:8.12-8.15: error: `case` clauses have incompatible types [E0308]\n"
+        );
+        assert_eq!(
+            render(OUTPUT_FORMAT_MSVC, FORMAT_OPTION_SECONDARY_NOTES),
+            "src/FizzBuzz.fun(8,12): error E0308: `case` clauses have incompatible types\n\
+             src/FizzBuzz.fun(5,12): note: this is found to be of type `String`\n"
+        );
+
+        let message = "`case` clauses have\nincompatible types".as_bytes();
+        let secondary_msg = "this is found\r\nto be `String`".as_bytes();
+        let multi_line = unsafe {
+            let diagnostic = CodespanDiagnostic::codespan_new_diagnostic(
+                SEVERITY_ERROR,
+                message.as_ptr(),
+                message.len(),
+                Some(collect_callback),
+            );
+            CodespanDiagnostic::codespan_diagnostic_set_primary(
+                diagnostic,
+                file_id,
+                163,
+                166,
+                ptr::null(),
+                0,
+            );
+            CodespanDiagnostic::codespan_diagnostic_add_secondary(
+                diagnostic,
+                file_id,
+                105,
+                115,
+                secondary_msg.as_ptr(),
+                secondary_msg.len(),
+            );
+            CodespanDiagnostic::codespan_diagnostic_set_format(
+                diagnostic,
+                OUTPUT_FORMAT_GNU,
+                FORMAT_OPTION_SECONDARY_NOTES,
+            );
+            diagnostic
+        };
+        let mut output = String::new();
+        unsafe {
+            CodespanDiagnostic::codespan_write_diagnostic(
+                &mut output as *mut _ as *mut libc::c_void,
+                multi_line,
+                src_map,
+                0,
+            );
+        }
+        assert_eq!(
+            output,
+            "src/FizzBuzz.fun:8:12: error: `case` clauses have incompatible types\n\
+             src/FizzBuzz.fun:5:12: note: this is found to be `String`\n"
+        );
+
+        unsafe {
+            CodespanDiagnostic::codespan_delete_diagnostic(multi_line);
+            CodespanDiagnostic::codespan_delete_diagnostic(diagnostic);
+            CodespanSourceMap::codespan_delete_source_map(src_map)
+        }
+    }
+
+    #[test]
+    fn fingerprint_test() {
+        let (mut simple_map, file_id) = fizz_buzz_map();
+        let shifted_source = format!(
+            "-- FizzBuzz\n\n{}",
+            simple_map.get(file_id).unwrap().source()
+        );
+        let shifted_id = simple_map.add("src/FizzBuzz.fun".to_owned(), shifted_source);
+        let src_map = new_source_map(&mut simple_map);
+
+        let message = "`case` clauses have incompatible types".as_bytes();
+        let fingerprint = |file_id, start| unsafe {
+            let diagnostic = CodespanDiagnostic::codespan_new_diagnostic(
+                SEVERITY_ERROR,
+                message.as_ptr(),
+                message.len(),
+                Some(collect_callback),
+            );
+            CodespanDiagnostic::codespan_diagnostic_set_primary(
+                diagnostic,
+                file_id,
+                start,
+                start + 3,
+                ptr::null(),
+                0,
+            );
+            let fingerprint =
+                CodespanDiagnostic::codespan_diagnostic_fingerprint(diagnostic, src_map);
+            CodespanDiagnostic::codespan_delete_diagnostic(diagnostic);
+            fingerprint
+        };
+
+        assert_eq!(fingerprint(file_id, 163), fingerprint(shifted_id, 176));
+        assert_ne!(fingerprint(file_id, 163), fingerprint(file_id, 56));
+
+        unsafe { CodespanSourceMap::codespan_delete_source_map(src_map) };
+    }
+
+    #[test]
+    fn baseline_test() {
+        let (mut simple_map, file_id) = fizz_buzz_map();
+        let src_map = new_source_map(&mut simple_map);
+        let old_diagnostic = new_fizz_buzz_diagnostic(file_id);
+
+        let message = "unused binding `num`".as_bytes();
+        let new_diagnostic = unsafe {
+            let diagnostic = CodespanDiagnostic::codespan_new_diagnostic(
+                SEVERITY_ERROR,
+                message.as_ptr(),
+                message.len(),
+                Some(collect_callback),
+            );
+            CodespanDiagnostic::codespan_diagnostic_set_primary(
+                diagnostic,
+                file_id,
+                56,
+                59,
+                ptr::null(),
+                0,
+            );
+            diagnostic
+        };
+
+        let mut baseline_file = String::new();
+        unsafe {
+            let emitter = CodespanEmitter::codespan_new_emitter(src_map);
+            let mut output = String::new();
+            assert_eq!(
+                CodespanEmitter::codespan_emitter_emit(
+                    &mut output as *mut _ as *mut libc::c_void,
+                    emitter,
+                    old_diagnostic,
+                    0,
+                ),
+                STATUS_OK
+            );
+            let status = CodespanEmitter::codespan_emitter_write_baseline(
+                &mut baseline_file as *mut _ as *mut libc::c_void,
+                emitter,
+                Some(collect_callback),
+            );
+            assert_eq!(status, STATUS_OK);
+            CodespanEmitter::codespan_delete_emitter(emitter);
+        }
+        assert!(baseline_file.starts_with("# codespan baseline v1\n"));
+        assert_eq!(baseline_file.lines().count(), 2);
+
+        let stale_entry = "00000000000000ff\tE0001\tsrc/Old.fun\tremoved\n";
+        let baseline_file = baseline_file + stale_entry;
+        unsafe {
+            let baseline = CodespanBaseline::codespan_new_baseline();
+            let invalid = b"# codespan baseline v1\n\xff\n";
+            assert!(!CodespanBaseline::codespan_baseline_load(
+                baseline,
+                invalid.as_ptr(),
+                invalid.len(),
+            ));
+            assert!(CodespanBaseline::codespan_baseline_load(
+                baseline,
+                baseline_file.as_ptr(),
+                baseline_file.len(),
+            ));
+
+            let emitter = CodespanEmitter::codespan_new_emitter(src_map);
+            CodespanEmitter::codespan_emitter_set_baseline(
+                emitter,
+                baseline,
+                BASELINE_MODE_SUPPRESS,
+            );
+            let mut output = String::new();
+            let user_data = &mut output as *mut _ as *mut libc::c_void;
+            assert_eq!(
+                CodespanEmitter::codespan_emitter_emit(user_data, emitter, old_diagnostic, 0),
+                STATUS_FILTERED
+            );
+            assert_eq!(
+                CodespanEmitter::codespan_emitter_emit(user_data, emitter, new_diagnostic, 0),
+                STATUS_OK
+            );
+            assert!(!output.contains("incompatible types"));
+            assert!(output.contains("unused binding `num`"));
+            assert_eq!(
+                CodespanEmitter::codespan_emitter_emit(user_data, emitter, old_diagnostic, 0),
+                STATUS_OK
+            );
+
+            let mut unused = String::new();
+            assert_eq!(
+                CodespanBaseline::codespan_baseline_unused_count(baseline),
+                1
+            );
+            CodespanBaseline::codespan_baseline_write_unused(
+                &mut unused as *mut _ as *mut libc::c_void,
+                baseline,
+                Some(collect_callback),
+            );
+            assert!(unused.ends_with(stale_entry));
+            CodespanEmitter::codespan_delete_emitter(emitter);
+            CodespanBaseline::codespan_delete_baseline(baseline);
+
+            let baseline = CodespanBaseline::codespan_new_baseline();
+            CodespanBaseline::codespan_baseline_load(
+                baseline,
+                baseline_file.as_ptr(),
+                baseline_file.len(),
+            );
+            let emitter = CodespanEmitter::codespan_new_emitter(src_map);
+            CodespanEmitter::codespan_emitter_set_baseline(
+                emitter,
+                baseline,
+                BASELINE_MODE_DOWNGRADE,
+            );
+            let mut output = String::new();
+            assert_eq!(
+                CodespanEmitter::codespan_emitter_emit(
+                    &mut output as *mut _ as *mut libc::c_void,
+                    emitter,
+                    old_diagnostic,
+                    0,
+                ),
+                STATUS_OK
+            );
+            assert!(output.starts_with("note[E0308]"));
+            CodespanEmitter::codespan_delete_emitter(emitter);
+            CodespanBaseline::codespan_delete_baseline(baseline);
+        }
+
+        unsafe {
+            CodespanDiagnostic::codespan_delete_diagnostic(old_diagnostic);
+            CodespanDiagnostic::codespan_delete_diagnostic(new_diagnostic);
+            CodespanSourceMap::codespan_delete_source_map(src_map)
+        }
+    }
+
+    #[test]
+    fn suppression_test() {
+        let mut simple_map = SimpleMap::new();
+        let source = unindent(
+            "
+            -- lint-ignore: E0308
+            x = bad
+            y = bad -- noqa: W12
+            z = bad -- noqa
+            -- lint-ignore: E9999
+            w = bad
+            ",
+        );
+        let file_id = simple_map.add("lint.fun".to_owned(), source.clone());
+        let ignored_id = simple_map.add(
+            "generated.fun".to_owned(),
+            "-- lint-ignore-file\nv = bad\n".to_owned(),
+        );
+        let src_map = new_source_map(&mut simple_map);
+
+        let message = "bad value".as_bytes();
+        let new_diagnostic = |file_id, start: usize, code: &str| unsafe {
+            let diagnostic = CodespanDiagnostic::codespan_new_diagnostic(
+                SEVERITY_ERROR,
+                message.as_ptr(),
+                message.len(),
+                Some(collect_callback),
+            );
+            CodespanDiagnostic::codespan_diagnostic_set_code(diagnostic, code.as_ptr(), code.len());
+            CodespanDiagnostic::codespan_diagnostic_set_primary(
+                diagnostic,
+                file_id,
+                start,
+                start + 3,
+                ptr::null(),
+                0,
+            );
+            diagnostic
+        };
+        let diagnostics = [
+            (
+                new_diagnostic(file_id, source.find("x = bad").unwrap() + 4, "E0308"),
+                false,
+            ),
+            (
+                new_diagnostic(file_id, source.find("y = bad").unwrap() + 4, "W12"),
+                false,
+            ),
+            (
+                new_diagnostic(file_id, source.find("z = bad").unwrap() + 4, "E0308"),
+                false,
+            ),
+            (
+                new_diagnostic(file_id, source.find("w = bad").unwrap() + 4, "E0308"),
+                true,
+            ),
+            (new_diagnostic(ignored_id, 24, "E0308"), false),
+        ];
+
+        let (comment, next_line, same_line, file) =
+            ("--", "lint-ignore", "noqa", "lint-ignore-file");
+        let syntax = CodespanSuppressionSyntax {
+            line_comment: comment.as_ptr(),
+            line_comment_length: comment.len(),
+            next_line_marker: next_line.as_ptr(),
+            next_line_marker_length: next_line.len(),
+            same_line_marker: same_line.as_ptr(),
+            same_line_marker_length: same_line.len(),
+            file_marker: file.as_ptr(),
+            file_marker_length: file.len(),
+        };
+
+        let mut output = String::new();
+        let mut unused = String::new();
+        unsafe {
+            let emitter = CodespanEmitter::codespan_new_emitter(src_map);
+            CodespanEmitter::codespan_emitter_set_default_suppression_syntax(emitter, &syntax);
+            for (diagnostic, emitted) in diagnostics {
+                assert_eq!(
+                    CodespanEmitter::codespan_emitter_emit(
+                        &mut output as *mut _ as *mut libc::c_void,
+                        emitter,
+                        diagnostic,
+                        0,
+                    ) == STATUS_OK,
+                    emitted
+                );
+                CodespanDiagnostic::codespan_delete_diagnostic(diagnostic);
+            }
+            let status = CodespanEmitter::codespan_emitter_write_unused_suppressions(
+                &mut unused as *mut _ as *mut libc::c_void,
+                emitter,
+                0,
+                Some(collect_callback),
+            );
+            assert_eq!(status, STATUS_OK);
+            CodespanEmitter::codespan_delete_emitter(emitter);
+        }
+
+        assert!(output.contains("lint.fun:6:5"));
+        assert!(unused.starts_with("warning: unused suppression `-- lint-ignore: E9999`"));
+        assert!(unused.contains("lint.fun:5:1"));
+
+        unsafe { CodespanSourceMap::codespan_delete_source_map(src_map) };
+
+        // Suppressions follow the host's line table, here one that breaks
+        // lines at `\r` only.
+        unsafe extern "C" fn cr_line_index(
+            user_data: *mut libc::c_void,
+            file_id: FileId,
+            byte_index: ByteIndex,
+        ) -> LineIndex {
+            let files = unsafe { &*(user_data as *const SimpleMap) };
+            let source = files.get(file_id).unwrap().source();
+            source[..byte_index].matches('\r').count()
+        }
+
+        unsafe extern "C" fn cr_line_range(
+            user_data: *mut libc::c_void,
+            file_id: FileId,
+            line_index: LineIndex,
+            start: *mut libc::size_t,
+            end: *mut libc::size_t,
+        ) {
+            let files = unsafe { &*(user_data as *const SimpleMap) };
+            let source = files.get(file_id).unwrap().source();
+            let mut line_starts = std::iter::once(0)
+                .chain(source.match_indices('\r').map(|(index, _)| index + 1))
+                .chain(std::iter::once(source.len()));
+            unsafe {
+                *start = line_starts.nth(line_index).unwrap();
+                *end = line_starts.next().unwrap_or(source.len());
+            }
+        }
+
+        let mut cr_map = SimpleMap::new();
+        let cr_source = "a = ok\r-- lint-ignore\rx = bad\r";
+        let cr_id = cr_map.add("cr.fun".to_owned(), cr_source.to_owned());
+        let mut output = String::new();
+        unsafe {
+            let src_map = CodespanSourceMap::codespan_new_source_map(
+                &mut cr_map as *mut _ as *mut libc::c_void,
+                Some(file_name),
+                Some(source_code),
+                Some(cr_line_index),
+                Some(cr_line_range),
+                None,
+                None,
+            );
+            let emitter = CodespanEmitter::codespan_new_emitter(src_map);
+            CodespanEmitter::codespan_emitter_set_default_suppression_syntax(emitter, &syntax);
+            let diagnostic = new_diagnostic(cr_id, cr_source.find("x = bad").unwrap() + 4, "E1");
+            assert_eq!(
+                CodespanEmitter::codespan_emitter_emit(
+                    &mut output as *mut _ as *mut libc::c_void,
+                    emitter,
+                    diagnostic,
+                    0,
+                ),
+                STATUS_FILTERED
+            );
+            CodespanDiagnostic::codespan_delete_diagnostic(diagnostic);
+            CodespanEmitter::codespan_delete_emitter(emitter);
+            CodespanSourceMap::codespan_delete_source_map(src_map);
+        }
+    }
+
+    #[test]
+    fn dedup_test() {
+        let (mut simple_map, file_id) = fizz_buzz_map();
+        let src_map = new_source_map(&mut simple_map);
+        let first = new_fizz_buzz_diagnostic(file_id);
+        let duplicate = new_fizz_buzz_diagnostic(file_id);
+        let extra_note = new_fizz_buzz_diagnostic(file_id);
+        let note = "found in a later pass".as_bytes();
+        unsafe {
+            CodespanDiagnostic::codespan_diagnostic_add_note(extra_note, note.as_ptr(), note.len())
+        };
+
+        let mut output = String::new();
+        let user_data = &mut output as *mut _ as *mut libc::c_void;
+        unsafe {
+            let emitter = CodespanEmitter::codespan_new_emitter(src_map);
+            CodespanEmitter::codespan_emitter_set_dedup_mode(emitter, DEDUP_MODE_EXACT);
+            assert_eq!(
+                CodespanEmitter::codespan_emitter_emit(user_data, emitter, first, 0),
+                STATUS_OK
+            );
+            assert_eq!(
+                CodespanEmitter::codespan_emitter_emit(user_data, emitter, duplicate, 0),
+                STATUS_FILTERED
+            );
+            assert_eq!(
+                CodespanEmitter::codespan_emitter_emit(user_data, emitter, extra_note, 0),
+                STATUS_OK
+            );
+            assert_eq!(
+                CodespanEmitter::codespan_emitter_deduplicated_count(emitter),
+                1
+            );
+            CodespanEmitter::codespan_delete_emitter(emitter);
+
+            let emitter = CodespanEmitter::codespan_new_emitter(src_map);
+            CodespanEmitter::codespan_emitter_set_dedup_mode(emitter, DEDUP_MODE_PRIMARY_SPAN);
+            assert_eq!(
+                CodespanEmitter::codespan_emitter_emit(user_data, emitter, first, 0),
+                STATUS_OK
+            );
+            assert_eq!(
+                CodespanEmitter::codespan_emitter_emit(user_data, emitter, extra_note, 0),
+                STATUS_FILTERED
+            );
+            assert_eq!(
+                CodespanEmitter::codespan_emitter_deduplicated_count(emitter),
+                1
+            );
+            CodespanEmitter::codespan_delete_emitter(emitter);
+        }
+
+        unsafe {
+            CodespanDiagnostic::codespan_delete_diagnostic(first);
+            CodespanDiagnostic::codespan_delete_diagnostic(duplicate);
+            CodespanDiagnostic::codespan_delete_diagnostic(extra_note);
+            CodespanSourceMap::codespan_delete_source_map(src_map)
+        }
+    }
+
+    #[test]
+    fn failed_emit_test() {
+        thread_local! {
+            static VANISHED: Cell<bool> = const { Cell::new(false) };
+        }
+
+        // The file goes away once rendering has started, after the emitter
+        // checked that it is available.
+        unsafe extern "C" fn vanishing_source_code(
+            user_data: *mut libc::c_void,
+            file_id: FileId,
+            source_code_length: *mut libc::size_t,
+        ) -> *const u8 {
+            if VANISHED.get() {
+                return ptr::null();
+            }
+            unsafe { source_code(user_data, file_id, source_code_length) }
+        }
+
+        unsafe extern "C" fn vanishing_line_number(
+            _user_data: *mut libc::c_void,
+            _file_id: FileId,
+            line_index: LineIndex,
+        ) -> libc::size_t {
+            VANISHED.set(true);
+            line_index + 1
+        }
+
+        let (mut simple_map, file_id) = fizz_buzz_map();
+        let src_map = unsafe {
+            CodespanSourceMap::codespan_new_source_map(
+                &mut simple_map as *mut _ as *mut libc::c_void,
+                Some(file_name),
+                Some(vanishing_source_code),
+                Some(line_index),
+                Some(line_range),
+                Some(vanishing_line_number),
+                None,
+            )
+        };
+        let diagnostic = new_fizz_buzz_diagnostic(file_id);
+
+        let mut output = String::new();
+        let user_data = &mut output as *mut _ as *mut libc::c_void;
+        unsafe {
+            // A diagnostic that failed to render is not recorded, so it is
+            // not dropped as a duplicate when it is emitted again.
+            let emitter = CodespanEmitter::codespan_new_emitter(src_map);
+            CodespanEmitter::codespan_emitter_set_dedup_mode(emitter, DEDUP_MODE_EXACT);
+            VANISHED.set(false);
+            assert_eq!(
+                CodespanEmitter::codespan_emitter_emit(user_data, emitter, diagnostic, 0),
+                STATUS_FILE_MISSING
+            );
+            let mut baseline_file = String::new();
+            let status = CodespanEmitter::codespan_emitter_write_baseline(
+                &mut baseline_file as *mut _ as *mut libc::c_void,
+                emitter,
+                Some(collect_callback),
+            );
+            assert_eq!(status, STATUS_OK);
+            assert_eq!(baseline_file, "# codespan baseline v1\n");
+
+            VANISHED.set(false);
+            assert_eq!(
+                CodespanEmitter::codespan_emitter_emit(user_data, emitter, diagnostic, 0),
+                STATUS_FILE_MISSING
+            );
+            assert_eq!(
+                CodespanEmitter::codespan_emitter_deduplicated_count(emitter),
+                0
+            );
+            assert_eq!(output, "");
+            CodespanEmitter::codespan_delete_emitter(emitter);
+        }
+
+        // A sink that fails does not keep the diagnostic from the others.
+        let mut term_output = String::new();
+        let mut sarif_output = String::new();
+        unsafe {
+            let emitter = CodespanEmitter::codespan_new_emitter(src_map);
+            CodespanEmitter::codespan_emitter_add_sink(
+                emitter,
+                CodespanSink::codespan_new_sink(
+                    &mut term_output as *mut _ as *mut libc::c_void,
+                    OUTPUT_FORMAT_TERM,
+                    0,
+                    0,
+                    Some(collect_callback),
+                ),
+            );
+            CodespanEmitter::codespan_emitter_add_sink(
+                emitter,
+                CodespanSink::codespan_new_sarif_sink(
+                    &mut sarif_output as *mut _ as *mut libc::c_void,
+                    ptr::null(),
+                    Some(collect_callback),
+                ),
+            );
+            VANISHED.set(false);
+            assert_eq!(
+                CodespanEmitter::codespan_emitter_emit(user_data, emitter, diagnostic, 0),
+                STATUS_FILE_MISSING
+            );
+            VANISHED.set(false);
+            CodespanEmitter::codespan_emitter_finish(emitter);
+            CodespanEmitter::codespan_delete_emitter(emitter);
+        }
+        assert_eq!(term_output, "");
+        let log: serde_json::Value = serde_json::from_str(&sarif_output).unwrap();
+        assert_eq!(log["runs"][0]["results"].as_array().unwrap().len(), 1);
+
+        unsafe {
+            CodespanDiagnostic::codespan_delete_diagnostic(diagnostic);
+            CodespanSourceMap::codespan_delete_source_map(src_map)
+        }
+    }
+
+    #[test]
+    fn diagnostic_list_test() {
+        let mut simple_map = SimpleMap::new();
+        let a_id = simple_map.add("a.fun".to_owned(), "one\ntwo\n".to_owned());
+        let b_id = simple_map.add("b.fun".to_owned(), "three\n".to_owned());
+        let src_map = new_source_map(&mut simple_map);
+
+        let new_diagnostic = |severity, message: &str, file_id, start: usize| unsafe {
+            let diagnostic = CodespanDiagnostic::codespan_new_diagnostic(
+                severity,
+                message.as_ptr(),
+                message.len(),
+                Some(collect_callback),
+            );
+            CodespanDiagnostic::codespan_diagnostic_set_config(
+                diagnostic,
+                DISPLAY_STYLE_SHORT,
+                CHAR_STYLE_FANCY,
+                4,
+            );
+            CodespanDiagnostic::codespan_diagnostic_set_primary(
+                diagnostic,
+                file_id,
+                start,
+                start + 3,
+                ptr::null(),
+                0,
+            );
+            diagnostic
+        };
+
+        let write = |list, options| {
+            let mut output = String::new();
+            unsafe {
+                CodespanDiagnosticList::codespan_diagnostic_list_write(
+                    &mut output as *mut _ as *mut libc::c_void,
+                    list,
+                    src_map,
+                    0,
+                    options,
+                    Some(collect_callback),
+                )
+            };
+            output
+        };
+
+        unsafe {
+            let list = CodespanDiagnosticList::codespan_new_diagnostic_list();
+            for diagnostic in [
+                new_diagnostic(SEVERITY_WARNING, "in b", b_id, 0),
+                new_diagnostic(SEVERITY_WARNING, "second in a", a_id, 4),
+                new_diagnostic(SEVERITY_ERROR, "first in a", a_id, 0),
+            ] {
+                CodespanDiagnosticList::codespan_diagnostic_list_push(list, diagnostic);
+            }
+            assert_eq!(
+                CodespanDiagnosticList::codespan_diagnostic_list_length(list),
+                3
+            );
+
+            CodespanDiagnosticList::codespan_diagnostic_list_sort(
+                list,
+                src_map,
+                SORT_ORDER_POSITION,
+            );
+            assert_eq!(
+                write(list, LIST_OPTION_FILE_HEADERS),
+                unindent(
+                    "
+                    ==> a.fun <==
+                    a.fun:1:1: error: first in a
+                    a.fun:2:1: warning: second in a
+                    ==> b.fun <==
+                    b.fun:1:1: warning: in b
+                    "
+                )
+            );
+
+            CodespanDiagnosticList::codespan_diagnostic_list_sort(
+                list,
+                src_map,
+                SORT_ORDER_SEVERITY,
+            );
+            assert!(write(list, 0).starts_with("a.fun:1:1: error: first in a\n"));
+
+            CodespanDiagnosticList::codespan_delete_diagnostic_list(list);
+            CodespanSourceMap::codespan_delete_source_map(src_map);
+        }
+    }
+
+    #[test]
+    fn job_test() {
+        let (mut simple_map, file_id) = fizz_buzz_map();
+        let src_map = new_source_map(&mut simple_map);
+        let diagnostics: Vec<*mut CodespanDiagnostic> = (0..4)
+            .map(|job_index| {
+                let message = format!("job {job_index}");
+                unsafe {
+                    let diagnostic = CodespanDiagnostic::codespan_new_diagnostic(
+                        SEVERITY_ERROR,
+                        message.as_ptr(),
+                        message.len(),
+                        Some(collect_callback),
+                    );
+                    CodespanDiagnostic::codespan_diagnostic_set_primary(
+                        diagnostic,
+                        file_id,
+                        163,
+                        166,
+                        ptr::null(),
+                        0,
+                    );
+                    CodespanDiagnostic::codespan_diagnostic_set_format(
+                        diagnostic,
+                        OUTPUT_FORMAT_GNU,
+                        0,
+                    );
+                    diagnostic
+                }
+            })
+            .collect();
+
+        let mut output = String::new();
+        let user_data = &mut output as *mut String as usize;
+        let emitter = unsafe { CodespanEmitter::codespan_new_emitter(src_map) };
+        let shared_emitter = unsafe { &*emitter };
+        let shared_diagnostics: Vec<&CodespanDiagnostic> =
+            diagnostics.iter().map(|d| unsafe { &**d }).collect();
+
+        // Jobs commit in whatever order the threads finish, but are written in
+        // job index order. Job 1 reports the same diagnostic twice.
+        std::thread::scope(|scope| {
+            for job_index in (0..4).rev() {
+                let diagnostics = &shared_diagnostics;
+                scope.spawn(move || unsafe {
+                    let job =
+                        CodespanEmitter::codespan_emitter_begin_job(shared_emitter, job_index);
+                    let repeat = if job_index == 1 { 2 } else { 1 };
+                    for _ in 0..repeat {
+                        assert_eq!(
+                            CodespanJob::codespan_job_emit(
+                                user_data as *mut libc::c_void,
+                                job,
+                                diagnostics[job_index],
+                                0
+                            ),
+                            STATUS_OK
+                        );
+                    }
+                    CodespanJob::codespan_job_commit(job);
+                });
+            }
+        });
+        assert_eq!(
+            output,
+            unindent(
+                r#"
+                src/FizzBuzz.fun:8:12: error: job 0
+                src/FizzBuzz.fun:8:12: error: job 1
+                src/FizzBuzz.fun:8:12: error: job 1
+                src/FizzBuzz.fun:8:12: error: job 2
+                src/FizzBuzz.fun:8:12: error: job 3
+            "#
+            )
+        );
+
+        // A job after a gap waits until the emitter is flushed.
+        output.clear();
+        unsafe {
+            let job = CodespanEmitter::codespan_emitter_begin_job(emitter, 6);
+            CodespanJob::codespan_job_emit(user_data as *mut libc::c_void, job, diagnostics[2], 0);
+            CodespanJob::codespan_job_commit(job);
+            assert_eq!(output, "");
+            CodespanEmitter::codespan_emitter_flush(emitter);
+            assert_eq!(output, "src/FizzBuzz.fun:8:12: error: job 2\n");
+        }
+
+        // Deleting an emitter writes the jobs it was still holding back.
+        output.clear();
+        unsafe {
+            let gapped = CodespanEmitter::codespan_new_emitter(src_map);
+            let job = CodespanEmitter::codespan_emitter_begin_job(gapped, 1);
+            CodespanJob::codespan_job_emit(user_data as *mut libc::c_void, job, diagnostics[3], 0);
+            CodespanJob::codespan_job_commit(job);
+            assert_eq!(output, "");
+            CodespanEmitter::codespan_delete_emitter(gapped);
+            assert_eq!(output, "src/FizzBuzz.fun:8:12: error: job 3\n");
+        }
+
+        output.clear();
+        unsafe {
+            CodespanEmitter::codespan_emitter_set_job_order(emitter, JOB_ORDER_COMMIT);
+            let first = CodespanEmitter::codespan_emitter_begin_job(emitter, 0);
+            let second = CodespanEmitter::codespan_emitter_begin_job(emitter, 1);
+            CodespanJob::codespan_job_emit(
+                user_data as *mut libc::c_void,
+                first,
+                diagnostics[0],
+                0,
+            );
+            CodespanJob::codespan_job_emit(
+                user_data as *mut libc::c_void,
+                second,
+                diagnostics[1],
+                0,
+            );
+            CodespanJob::codespan_job_commit(second);
+            CodespanJob::codespan_job_commit(first);
+            CodespanEmitter::codespan_delete_emitter(emitter);
+        }
+        assert_eq!(
+            output,
+            unindent(
+                r#"
+                src/FizzBuzz.fun:8:12: error: job 1
+                src/FizzBuzz.fun:8:12: error: job 0
+            "#
+            )
+        );
+
+        unsafe {
+            for diagnostic in diagnostics {
+                CodespanDiagnostic::codespan_delete_diagnostic(diagnostic);
+            }
+            CodespanSourceMap::codespan_delete_source_map(src_map)
+        }
+    }
+
+    struct Reentrant {
+        emitter: *const CodespanEmitter,
+        diagnostic: *const CodespanDiagnostic,
+        output: String,
+    }
+
+    unsafe extern "C" fn reentrant_callback(
+        user_data: *mut libc::c_void,
+        utf8_output: *const u8,
+        output_length: libc::size_t,
+    ) {
+        let reentrant = unsafe { &mut *(user_data as *mut Reentrant) };
+        let output =
+            str::from_utf8(unsafe { slice::from_raw_parts(utf8_output, output_length) }).unwrap();
+        let first = reentrant.output.is_empty();
+        reentrant.output.push_str(output);
+        if first {
+            unsafe {
+                CodespanEmitter::codespan_emitter_emit(
+                    user_data,
+                    reentrant.emitter,
+                    reentrant.diagnostic,
+                    0,
+                );
+                CodespanEmitter::codespan_emitter_flush(reentrant.emitter);
+            }
+        }
+    }
+
+    #[test]
+    fn reentrant_emitter_test() {
+        let (mut simple_map, file_id) = fizz_buzz_map();
+        let src_map = new_source_map(&mut simple_map);
+        let message = "reentrant";
+        unsafe {
+            let diagnostic = CodespanDiagnostic::codespan_new_diagnostic(
+                SEVERITY_ERROR,
+                message.as_ptr(),
+                message.len(),
+                Some(reentrant_callback),
+            );
+            CodespanDiagnostic::codespan_diagnostic_set_primary(
+                diagnostic,
+                file_id,
+                163,
+                166,
+                ptr::null(),
+                0,
+            );
+            CodespanDiagnostic::codespan_diagnostic_set_format(diagnostic, OUTPUT_FORMAT_GNU, 0);
+            let emitter = CodespanEmitter::codespan_new_emitter(src_map);
+            let mut reentrant = Reentrant {
+                emitter,
+                diagnostic,
+                output: String::new(),
+            };
+
+            // The writer emits another diagnostic from inside the callback,
+            // which is written once the outer write returns.
+            assert_eq!(
+                CodespanEmitter::codespan_emitter_emit(
+                    &mut reentrant as *mut Reentrant as *mut libc::c_void,
+                    emitter,
+                    diagnostic,
+                    0,
+                ),
+                STATUS_OK
+            );
+            assert_eq!(
+                reentrant.output,
+                unindent(
+                    r#"
+                    src/FizzBuzz.fun:8:12: error: reentrant
+                    src/FizzBuzz.fun:8:12: error: reentrant
+                "#
+                )
+            );
+
+            CodespanEmitter::codespan_delete_emitter(emitter);
+            CodespanDiagnostic::codespan_delete_diagnostic(diagnostic);
+            CodespanSourceMap::codespan_delete_source_map(src_map);
+        }
+    }
+
+    struct Stream {
+        chunks: Vec<String>,
+        flushes: usize,
+        fail_after: usize,
+    }
+
+    unsafe extern "C" fn stream_callback(
+        user_data: *mut libc::c_void,
+        utf8_output: *const u8,
+        output_length: libc::size_t,
+    ) -> libc::c_int {
+        let stream = unsafe { &mut *(user_data as *mut Stream) };
+        if stream.chunks.len() == stream.fail_after {
+            return libc::EPIPE;
+        }
+        stream.chunks.push(
+            str::from_utf8(unsafe { slice::from_raw_parts(utf8_output, output_length) })
+                .unwrap()
+                .to_owned(),
+        );
+        0
+    }
+
+    unsafe extern "C" fn flush_callback(user_data: *mut libc::c_void) -> libc::c_int {
+        unsafe { &mut *(user_data as *mut Stream) }.flushes += 1;
+        0
+    }
+
+    #[test]
+    fn stream_test() {
+        let (mut simple_map, file_id) = fizz_buzz_map();
+        let src_map = new_source_map(&mut simple_map);
+        let diagnostic = new_fizz_buzz_diagnostic(file_id);
+
+        let mut output = String::new();
+        unsafe {
+            CodespanDiagnostic::codespan_write_diagnostic(
+                &mut output as *mut _ as *mut libc::c_void,
+                diagnostic,
+                src_map,
+                0,
+            )
+        };
+
+        let mut stream = Stream {
+            chunks: Vec::new(),
+            flushes: 0,
+            fail_after: usize::MAX,
+        };
+        let status = unsafe {
+            CodespanDiagnostic::codespan_write_diagnostic_stream(
+                &mut stream as *mut _ as *mut libc::c_void,
+                diagnostic,
+                src_map,
+                0,
+                Some(stream_callback),
+                Some(flush_callback),
+            )
+        };
+        assert_eq!(status, STATUS_OK);
+        assert!(stream.chunks.len() > 1);
+        assert_eq!(stream.chunks.concat(), output);
+        assert_eq!(stream.flushes, 1);
+
+        // A failing writer aborts rendering and its error is returned.
+        let mut stream = Stream {
+            chunks: Vec::new(),
+            flushes: 0,
+            fail_after: 2,
+        };
+        let status = unsafe {
+            CodespanDiagnostic::codespan_write_diagnostic_stream(
+                &mut stream as *mut _ as *mut libc::c_void,
+                diagnostic,
+                src_map,
+                0,
+                Some(stream_callback),
+                Some(flush_callback),
+            )
+        };
+        assert_eq!(status, libc::EPIPE);
+        assert_eq!(stream.chunks.len(), 2);
+        assert_eq!(stream.flushes, 0);
+
+        unsafe {
+            CodespanDiagnostic::codespan_delete_diagnostic(diagnostic);
+            CodespanSourceMap::codespan_delete_source_map(src_map)
+        }
+    }
+
+    fn read_pipe(fd: libc::c_int) -> String {
+        let mut buffer = vec![0u8; 4096];
+        let read = unsafe { libc::read(fd, buffer.as_mut_ptr().cast(), buffer.len()) };
+        buffer.truncate(read as usize);
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn writer_test() {
+        let (mut simple_map, file_id) = fizz_buzz_map();
+        let src_map = new_source_map(&mut simple_map);
+        let diagnostic = new_fizz_buzz_diagnostic(file_id);
+
+        let mut output = String::new();
+        unsafe {
+            CodespanDiagnostic::codespan_write_diagnostic(
+                &mut output as *mut _ as *mut libc::c_void,
+                diagnostic,
+                src_map,
+                0,
+            )
+        };
+
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let [read_fd, write_fd] = fds;
+
+        let status = unsafe {
+            CodespanDiagnostic::codespan_write_diagnostic_fd(
+                write_fd,
+                diagnostic,
+                src_map,
+                COLOR_NEVER,
+            )
+        };
+        assert_eq!(status, STATUS_OK);
+        assert_eq!(read_pipe(read_fd), output);
+
+        let status = unsafe {
+            CodespanDiagnostic::codespan_write_diagnostic_fd(
+                write_fd,
+                diagnostic,
+                src_map,
+                COLOR_ALWAYS,
+            )
+        };
+        assert_eq!(status, STATUS_OK);
+        assert!(read_pipe(read_fd).contains("\x1b["));
+
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.into())
+            }
+        };
+        // A pipe is not a terminal, so auto mode leaves the output plain
+        // unless color is forced.
+        assert!(!color::detect(Some(write_fd), env(&[])));
+        assert!(color::detect(
+            Some(write_fd),
+            env(&[("CLICOLOR_FORCE", "1"), ("NO_COLOR", "1")])
+        ));
+        assert!(!color::detect(None, env(&[("FORCE_COLOR", "0")])));
+        assert!(color::detect(None, env(&[("FORCE_COLOR", "true")])));
+
+        unsafe {
+            libc::close(read_fd);
+            libc::close(write_fd);
+        }
+
+        unsafe {
+            let file = libc::tmpfile();
+            let status = CodespanDiagnostic::codespan_write_diagnostic_file(
+                file,
+                diagnostic,
+                src_map,
+                COLOR_ALWAYS,
+            );
+            assert_eq!(status, STATUS_OK);
+            libc::rewind(file);
+            let mut buffer = vec![0u8; 4096];
+            let read = libc::fread(buffer.as_mut_ptr().cast(), 1, buffer.len(), file);
+            buffer.truncate(read);
+            assert!(str::from_utf8(&buffer).unwrap().contains("\x1b["));
+            libc::fclose(file);
+        }
+
+        unsafe {
+            CodespanDiagnostic::codespan_delete_diagnostic(diagnostic);
+            CodespanSourceMap::codespan_delete_source_map(src_map)
+        }
+    }
+
+    #[test]
+    fn render_to_string_test() {
+        let (mut simple_map, file_id) = fizz_buzz_map();
+        let src_map = new_source_map(&mut simple_map);
+        let diagnostic = new_fizz_buzz_diagnostic(file_id);
+
+        let mut output = String::new();
+        unsafe {
+            CodespanDiagnostic::codespan_write_diagnostic(
+                &mut output as *mut _ as *mut libc::c_void,
+                diagnostic,
+                src_map,
+                0,
+            )
+        };
+
+        unsafe {
+            let mut length = 0;
+            let string = CodespanDiagnostic::codespan_render_diagnostic_to_string(
+                diagnostic,
+                src_map,
+                0,
+                &mut length,
+            );
+            assert_eq!(
+                str::from_utf8(slice::from_raw_parts(string, length)).unwrap(),
+                output
+            );
+            assert_eq!(*string.add(length), 0);
+            CodespanDiagnostic::codespan_free_string(string);
+        }
+
+        // Asking for the size first, then rendering into a large enough buffer.
+        let required = unsafe {
+            CodespanDiagnostic::codespan_render_diagnostic_to_buffer(
+                diagnostic,
+                src_map,
+                0,
+                ptr::null_mut(),
+                0,
+            )
+        };
+        assert_eq!(required as usize, output.len());
+        let mut buffer = vec![0xFFu8; output.len() + 1];
+        unsafe {
+            CodespanDiagnostic::codespan_render_diagnostic_to_buffer(
+                diagnostic,
+                src_map,
+                0,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+            )
+        };
+        assert_eq!(&buffer[..output.len()], output.as_bytes());
+        assert_eq!(buffer[output.len()], 0);
+
+        // The fancy box drawing characters are three bytes long, and
+        // truncation must not split them.
+        let box_start = output.find('┌').unwrap();
+        let mut buffer = vec![0xFFu8; box_start + 2];
+        let required = unsafe {
+            CodespanDiagnostic::codespan_render_diagnostic_to_buffer(
+                diagnostic,
+                src_map,
+                0,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+            )
+        };
+        assert_eq!(required as usize, output.len());
+        assert_eq!(&buffer[..box_start], &output.as_bytes()[..box_start]);
+        assert_eq!(buffer[box_start], 0);
+
+        unsafe {
+            CodespanDiagnostic::codespan_delete_diagnostic(diagnostic);
+            CodespanSourceMap::codespan_delete_source_map(src_map)
+        }
+    }
+
+    #[test]
+    fn sink_test() {
+        let (mut simple_map, file_id) = fizz_buzz_map();
+        let src_map = new_source_map(&mut simple_map);
+        let diagnostic = new_fizz_buzz_diagnostic(file_id);
+
+        let mut unused_output = String::new();
+        let mut term_output = String::new();
+        let mut gnu_output = String::new();
+        let mut sarif_output = String::new();
+        unsafe {
+            let emitter = CodespanEmitter::codespan_new_emitter(src_map);
+
+            let term_sink = CodespanSink::codespan_new_sink(
+                &mut term_output as *mut _ as *mut libc::c_void,
+                OUTPUT_FORMAT_TERM,
+                0,
+                0,
+                Some(collect_callback),
+            );
+            CodespanSink::codespan_sink_set_config(
+                term_sink,
+                DISPLAY_STYLE_SHORT,
+                CHAR_STYLE_FANCY,
+                4,
+            );
+            CodespanEmitter::codespan_emitter_add_sink(emitter, term_sink);
+            CodespanEmitter::codespan_emitter_add_sink(
+                emitter,
+                CodespanSink::codespan_new_sink(
+                    &mut gnu_output as *mut _ as *mut libc::c_void,
+                    OUTPUT_FORMAT_GNU,
+                    0,
+                    0,
+                    Some(collect_callback),
+                ),
+            );
+            CodespanEmitter::codespan_emitter_add_sink(
+                emitter,
+                CodespanSink::codespan_new_sarif_sink(
+                    &mut sarif_output as *mut _ as *mut libc::c_void,
+                    ptr::null(),
+                    Some(collect_callback),
+                ),
+            );
+
+            assert_eq!(
+                CodespanEmitter::codespan_emitter_emit(
+                    &mut unused_output as *mut _ as *mut libc::c_void,
+                    emitter,
+                    diagnostic,
+                    0
+                ),
+                STATUS_OK
+            );
+            assert_eq!(
+                CodespanEmitter::codespan_emitter_emit(
+                    &mut unused_output as *mut _ as *mut libc::c_void,
+                    emitter,
+                    diagnostic,
+                    0
+                ),
+                STATUS_OK
+            );
+            assert_eq!(sarif_output, "");
+
+            CodespanEmitter::codespan_emitter_finish(emitter);
+            // Reports are only written once.
+            CodespanEmitter::codespan_emitter_finish(emitter);
+            CodespanEmitter::codespan_delete_emitter(emitter);
+        }
+
+        assert_eq!(unused_output, "");
+        assert_eq!(
+            term_output,
+            "src/FizzBuzz.fun:8:12: error[E0308]: `case` clauses have incompatible types\n"
+                .repeat(2)
+        );
+        assert_eq!(
+            gnu_output,
+            "src/FizzBuzz.fun:8:12: error: `case` clauses have incompatible types [E0308]\n"
+                .repeat(2)
+        );
+        let log: serde_json::Value = serde_json::from_str(&sarif_output).unwrap();
+        assert_eq!(log["runs"][0]["results"].as_array().unwrap().len(), 2);
+
+        unsafe {
+            CodespanDiagnostic::codespan_delete_diagnostic(diagnostic);
+            CodespanSourceMap::codespan_delete_source_map(src_map)
+        }
+    }
+
+    #[test]
+    fn config_test() {
+        let (mut simple_map, file_id) = fizz_buzz_map();
+        let src_map = new_source_map(&mut simple_map);
+        let diagnostic = new_fizz_buzz_diagnostic(file_id);
+
+        let mut output = String::new();
+        unsafe {
+            let config = CodespanConfig::codespan_new_config();
+            assert!(!CodespanConfig::codespan_config_set_style(
+                config,
+                7,
+                CHAR_STYLE_FANCY,
+                4
+            ));
+            assert_eq!(CodespanConfig::codespan_config_color(config), COLOR_AUTO);
+
+            let errors = (*config).apply_env(|name| {
+                match name {
+                    "CODESPAN_STYLE" => Some("short"),
+                    "CODESPAN_TAB_WIDTH" => Some("wide"),
+                    "CODESPAN_COLOR" => Some("always"),
+                    "CODESPAN_THEME" => Some(""),
+                    _ => None,
+                }
+                .map(String::from)
+            });
+            assert_eq!(
+                errors,
+                "invalid value `wide` for CODESPAN_TAB_WIDTH, expected a positive integer\n"
+            );
+            assert_eq!(CodespanConfig::codespan_config_color(config), COLOR_ALWAYS);
+
+            CodespanDiagnostic::codespan_diagnostic_apply_config(diagnostic, config);
+            CodespanDiagnostic::codespan_write_diagnostic(
+                &mut output as *mut _ as *mut libc::c_void,
+                diagnostic,
+                src_map,
+                0,
+            );
+            CodespanConfig::codespan_delete_config(config);
+        }
+        assert_eq!(
+            output,
+            "src/FizzBuzz.fun:8:12: error[E0308]: `case` clauses have incompatible types\n"
+        );
+
+        unsafe {
+            CodespanDiagnostic::codespan_delete_diagnostic(diagnostic);
+            CodespanSourceMap::codespan_delete_source_map(src_map)
+        }
+    }
+
+    #[test]
+    fn filesystem_source_map_test() {
+        let (mut simple_map, simple_id) = fizz_buzz_map();
+        let callback_map = new_source_map(&mut simple_map);
+        let diagnostic = new_fizz_buzz_diagnostic(simple_id);
+
+        let mut expected = String::new();
+        unsafe {
+            CodespanDiagnostic::codespan_write_diagnostic(
+                &mut expected as *mut _ as *mut libc::c_void,
+                diagnostic,
+                callback_map,
+                0,
+            )
+        };
+
+        let path = std::env::temp_dir()
+            .join(format!("codespan-ffi-{}-FizzBuzz.fun", std::process::id()))
+            .into_os_string()
+            .into_string()
+            .unwrap();
+        std::fs::write(&path, simple_map.source(simple_id).unwrap()).unwrap();
+        let expected = expected.replace("src/FizzBuzz.fun", &path);
+
+        unsafe {
+            let src_map = CodespanSourceMap::codespan_new_filesystem_source_map();
+            let fs_id =
+                CodespanSourceMap::codespan_source_map_add_path(src_map, path.as_ptr(), path.len());
+            assert_eq!(
+                CodespanSourceMap::codespan_source_map_add_path(src_map, path.as_ptr(), path.len()),
+                fs_id
+            );
+            let diagnostic = new_fizz_buzz_diagnostic(fs_id);
+
+            let mut output = String::new();
+            CodespanDiagnostic::codespan_write_diagnostic(
+                &mut output as *mut _ as *mut libc::c_void,
+                diagnostic,
+                src_map,
+                0,
+            );
+            assert_eq!(output, expected);
+
+            // The file has been loaded, so later changes are not seen.
+            std::fs::remove_file(&path).unwrap();
+            let mut output = String::new();
+            CodespanDiagnostic::codespan_write_diagnostic(
+                &mut output as *mut _ as *mut libc::c_void,
+                diagnostic,
+                src_map,
+                0,
+            );
+            assert_eq!(output, expected);
+
+            CodespanDiagnostic::codespan_delete_diagnostic(diagnostic);
+            CodespanSourceMap::codespan_delete_source_map(src_map);
+        }
+
+        unsafe {
+            let src_map = CodespanSourceMap::codespan_new_filesystem_source_map();
+            CodespanSourceMap::codespan_source_map_set_mmap_threshold(src_map, 1);
+            let fs_id =
+                CodespanSourceMap::codespan_source_map_add_path(src_map, path.as_ptr(), path.len());
+            let diagnostic = new_fizz_buzz_diagnostic(fs_id);
+
+            let mut output = String::new();
+            CodespanDiagnostic::codespan_write_diagnostic(
+                &mut output as *mut _ as *mut libc::c_void,
+                diagnostic,
+                src_map,
+                0,
+            );
+            // A missing file is rendered without snippets.
+            assert!(output.starts_with(&format!(
+                "error[E0308]: `case` clauses have incompatible types\n  ┌─ {path}: expected"
+            )));
+            assert!(output.ends_with(&format!(
+                "= source of `{path}` is unavailable: No such file or directory (os error 2)\n"
+            )));
+
+            // Failed reads are not cached.
+            std::fs::write(&path, simple_map.source(simple_id).unwrap()).unwrap();
+            let mut output = String::new();
+            CodespanDiagnostic::codespan_write_diagnostic(
+                &mut output as *mut _ as *mut libc::c_void,
+                diagnostic,
+                src_map,
+                0,
+            );
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(output, expected);
+
+            CodespanDiagnostic::codespan_delete_diagnostic(diagnostic);
+            CodespanSourceMap::codespan_delete_source_map(src_map);
+        }
+
+        unsafe {
+            CodespanDiagnostic::codespan_delete_diagnostic(diagnostic);
+            CodespanSourceMap::codespan_delete_source_map(callback_map)
+        }
+    }
+
+    #[test]
+    fn overlay_source_map_test() {
+        let (mut simple_map, file_id) = fizz_buzz_map();
+        let base_map = new_source_map(&mut simple_map);
+        let diagnostic = new_fizz_buzz_diagnostic(file_id);
+
+        let write = |src_map| {
+            let mut output = String::new();
+            unsafe {
+                CodespanDiagnostic::codespan_write_diagnostic(
+                    &mut output as *mut _ as *mut libc::c_void,
+                    diagnostic,
+                    src_map,
+                    0,
+                )
+            };
+            output
+        };
+
+        let original = write(base_map);
+        let path = "src/FizzBuzz.fun";
+        let buffer = simple_map
+            .source(file_id)
+            .unwrap()
+            .replace("_ _ => num", "_ _ => one");
+
+        unsafe {
+            let src_map = CodespanSourceMap::codespan_new_overlay_source_map(base_map);
+            assert!(CodespanSourceMap::codespan_source_map_set_overlay(
+                src_map,
+                path.as_ptr(),
+                path.len(),
+                buffer.as_ptr(),
+                buffer.len(),
+            ));
+            assert_eq!(write(src_map), original.replace("_ _ => num", "_ _ => one"));
+
+            assert!(CodespanSourceMap::codespan_source_map_clear_overlay(
+                src_map,
+                path.as_ptr(),
+                path.len(),
+            ));
+            assert!(!CodespanSourceMap::codespan_source_map_clear_overlay(
+                src_map,
+                path.as_ptr(),
+                path.len(),
+            ));
+            assert_eq!(write(src_map), original);
+
+            // Only overlay maps accept overlays.
+            assert!(!CodespanSourceMap::codespan_source_map_set_overlay(
+                base_map,
+                path.as_ptr(),
+                path.len(),
+                buffer.as_ptr(),
+                buffer.len(),
+            ));
+
+            CodespanSourceMap::codespan_delete_source_map(src_map);
+            CodespanDiagnostic::codespan_delete_diagnostic(diagnostic);
+            CodespanSourceMap::codespan_delete_source_map(base_map)
+        }
+    }
+
+    #[test]
+    fn owned_source_map_test() {
+        let (simple_map, file_id) = fizz_buzz_map();
+        let source = simple_map.source(file_id).unwrap();
+        let name = "src/FizzBuzz.fun";
+
+        let edit = |start, end, text: &'static str| CodespanTextEdit {
+            start,
+            end,
+            text: text.as_ptr(),
+            text_length: text.len(),
+        };
+
+        unsafe {
+            let src_map = CodespanSourceMap::codespan_new_owned_source_map();
+            let file_id = CodespanSourceMap::codespan_source_map_add_file(
+                src_map,
+                name.as_ptr(),
+                name.len(),
+                source.as_ptr(),
+                source.len(),
+            );
+            assert_eq!(
+                CodespanSourceMap::codespan_source_map_file_version(src_map, file_id),
+                1
+            );
+
+            let edits = [
+                edit(0, 0, "-- generated\n"),
+                edit(36, 50, "fizz : Nat\n→\nString"),
+                edit(100, 130, ""),
+                edit(10, 12, "\n\n"),
+            ];
+            assert!(CodespanSourceMap::codespan_source_map_apply_edits(
+                src_map,
+                file_id,
+                edits.as_ptr(),
+                edits.len(),
+            ));
+            assert_eq!(
+                CodespanSourceMap::codespan_source_map_file_version(src_map, file_id),
+                2
+            );
+
+            let mut expected = source.to_owned();
+            for edit in &edits {
+                let text = str::from_utf8(slice::from_raw_parts(edit.text, edit.text_length));
+                expected.replace_range(edit.start..edit.end, text.unwrap());
+            }
+            let mut expected_map = SimpleFiles::new();
+            let expected_id = expected_map.add(name, expected.clone());
+
+            assert_eq!((*src_map).source(file_id).unwrap(), expected);
+            for line in 0..=expected.lines().count() {
+                assert_eq!(
+                    (*src_map).line_range(file_id, line).unwrap(),
+                    expected_map.line_range(expected_id, line).unwrap()
+                );
+            }
+            for byte_index in 0..expected.len() {
+                assert_eq!(
+                    (*src_map).line_index(file_id, byte_index).unwrap(),
+                    expected_map.line_index(expected_id, byte_index).unwrap()
+                );
+            }
+
+            // `₁` is three bytes long, so this edit would split it.
+            let fizz = expected.find('₁').unwrap();
+            let edits = [edit(0, 1, "#"), edit(fizz + 1, fizz + 2, "1")];
+            assert!(!CodespanSourceMap::codespan_source_map_apply_edits(
+                src_map,
+                file_id,
+                edits.as_ptr(),
+                edits.len(),
+            ));
+            assert_eq!((*src_map).source(file_id).unwrap(), expected);
+            assert_eq!(
+                CodespanSourceMap::codespan_source_map_file_version(src_map, file_id),
+                2
+            );
+
+            CodespanSourceMap::codespan_delete_source_map(src_map);
+        }
+    }
+
+    #[test]
+    fn rebase_test() {
+        let (simple_map, file_id) = fizz_buzz_map();
+        let source = simple_map.source(file_id).unwrap();
+        let name = "src/FizzBuzz.fun";
+
+        let header = "-- generated\n";
+        let replacement = "x";
+        let edits = [
+            CodespanTextEdit {
+                start: 0,
+                end: 0,
+                text: header.as_ptr(),
+                text_length: header.len(),
+            },
+            CodespanTextEdit {
+                start: 120,
+                end: 121,
+                text: replacement.as_ptr(),
+                text_length: replacement.len(),
+            },
+        ];
+
+        unsafe {
+            let src_map = CodespanSourceMap::codespan_new_owned_source_map();
+            let file_id = CodespanSourceMap::codespan_source_map_add_file(
+                src_map,
+                name.as_ptr(),
+                name.len(),
+                source.as_ptr(),
+                source.len(),
+            );
+            assert!(CodespanSourceMap::codespan_source_map_apply_edits(
+                src_map,
+                file_id,
+                edits.as_ptr(),
+                edits.len(),
+            ));
+
+            let flagged = new_fizz_buzz_diagnostic(file_id);
+            let mut edited = 0;
+            let status = CodespanDiagnostic::codespan_diagnostic_rebase(
+                flagged,
+                file_id,
+                edits.as_ptr(),
+                edits.len(),
+                REBASE_MODE_FLAG,
+                &mut edited,
+            );
+            assert_eq!(status, STATUS_OK);
+            assert_eq!(edited, 1);
+            let labels = &(*flagged).diagnostic.labels;
+            assert_eq!(labels[0].range, 176..179);
+            assert_eq!(labels[1].range, 118..128);
+
+            let mut output = String::new();
+            CodespanDiagnostic::codespan_write_diagnostic(
+                &mut output as *mut _ as *mut libc::c_void,
+                flagged,
+                src_map,
+                0,
+            );
+            assert!(output.contains("┌─ src/FizzBuzz.fun:9:12"));
+            assert!(output.contains("6 │     0 0 => \"FxzzBuzz\"\n"));
+            assert!(output.contains("9 │     _ _ => num\n"));
+            assert!(output.contains(
+                "= the code this diagnostic refers to has been edited since it was produced"
+            ));
+
+            // Flagging the same diagnostic again does not repeat the note.
+            CodespanDiagnostic::codespan_diagnostic_rebase(
+                flagged,
+                file_id,
+                edits[1..].as_ptr(),
+                1,
+                REBASE_MODE_FLAG,
+                ptr::null_mut(),
+            );
+            assert_eq!((*flagged).diagnostic.notes.len(), 2);
+
+            let dropped = new_fizz_buzz_diagnostic(file_id);
+            let mut edited = 0;
+            let status = CodespanDiagnostic::codespan_diagnostic_rebase(
+                dropped,
+                file_id,
+                edits.as_ptr(),
+                edits.len(),
+                REBASE_MODE_DROP,
+                &mut edited,
+            );
+            assert_eq!(status, STATUS_OK);
+            assert_eq!(edited, 1);
+            let labels = &(*dropped).diagnostic.labels;
+            assert_eq!(labels.len(), 1);
+            assert_eq!(labels[0].range, 176..179);
+            assert_eq!((*dropped).diagnostic.notes.len(), 1);
+
+            // A snapshot holds the text from before the edits, so rebasing
+            // drops it and the diagnostic is rendered from the source map.
+            let snapshotted = new_fizz_buzz_diagnostic(file_id);
+            let status = CodespanDiagnostic::codespan_diagnostic_snapshot(snapshotted, src_map);
+            assert_eq!(status, STATUS_OK);
+            CodespanDiagnostic::codespan_diagnostic_rebase(
+                snapshotted,
+                file_id,
+                edits.as_ptr(),
+                edits.len(),
+                REBASE_MODE_DROP,
+                ptr::null_mut(),
+            );
+            let write = |diagnostic, src_map| {
+                let mut output = String::new();
+                CodespanDiagnostic::codespan_write_diagnostic(
+                    &mut output as *mut _ as *mut libc::c_void,
+                    diagnostic,
+                    src_map,
+                    0,
+                );
+                output
+            };
+            assert_eq!(write(snapshotted, ptr::null()), "");
+            assert_eq!(write(snapshotted, src_map), write(dropped, src_map));
+            CodespanDiagnostic::codespan_delete_diagnostic(snapshotted);
+
+            // Labels in other files are left alone.
+            let other = new_fizz_buzz_diagnostic(file_id + 1);
+            let mut edited = 0;
+            let status = CodespanDiagnostic::codespan_diagnostic_rebase(
+                other,
+                file_id,
+                edits.as_ptr(),
+                edits.len(),
+                REBASE_MODE_DROP,
+                &mut edited,
+            );
+            assert_eq!(status, STATUS_OK);
+            assert_eq!(edited, 0);
+            assert_eq!((&(*other).diagnostic.labels)[0].range, 163..166);
+
+            // Edits that end before they start are rejected as a whole.
+            let invalid = [
+                CodespanTextEdit {
+                    start: 10,
+                    end: 5,
+                    text: ptr::null(),
+                    text_length: 0,
+                },
+                CodespanTextEdit {
+                    start: 0,
+                    end: 0,
+                    text: header.as_ptr(),
+                    text_length: header.len(),
+                },
+            ];
+            let status = CodespanDiagnostic::codespan_diagnostic_rebase(
+                other,
+                file_id + 1,
+                invalid.as_ptr(),
+                invalid.len(),
+                REBASE_MODE_DROP,
+                ptr::null_mut(),
+            );
+            assert_eq!(status, STATUS_INVALID_ARGUMENT);
+            assert_eq!((&(*other).diagnostic.labels)[0].range, 163..166);
+
+            CodespanDiagnostic::codespan_delete_diagnostic(flagged);
+            CodespanDiagnostic::codespan_delete_diagnostic(dropped);
+            CodespanDiagnostic::codespan_delete_diagnostic(other);
+            CodespanSourceMap::codespan_delete_source_map(src_map);
+        }
+    }
+
+    #[test]
+    fn snapshot_test() {
+        let (mut simple_map, file_id) = fizz_buzz_map();
+        let src_map = new_source_map(&mut simple_map);
+        let diagnostic = new_fizz_buzz_diagnostic(file_id);
+
+        let mut expected = String::new();
+        unsafe {
+            CodespanDiagnostic::codespan_write_diagnostic(
+                &mut expected as *mut _ as *mut libc::c_void,
+                diagnostic,
+                src_map,
+                0,
+            );
+
+            // Without a snapshot a source map is required.
+            let mut output = String::new();
+            CodespanDiagnostic::codespan_write_diagnostic(
+                &mut output as *mut _ as *mut libc::c_void,
+                diagnostic,
+                ptr::null(),
+                0,
+            );
+            assert_eq!(output, "");
+
+            let status = CodespanDiagnostic::codespan_diagnostic_snapshot(diagnostic, src_map);
+            assert_eq!(status, STATUS_OK);
+            CodespanSourceMap::codespan_delete_source_map(src_map);
+        }
+        drop(simple_map);
+
+        // Diagnostics are not `Send`, so the pointer is passed as an address.
+        let address = diagnostic as usize;
+        let output = std::thread::spawn(move || {
+            let mut output = String::new();
+            unsafe {
+                CodespanDiagnostic::codespan_write_diagnostic(
+                    &mut output as *mut _ as *mut libc::c_void,
+                    address as *const CodespanDiagnostic,
+                    ptr::null(),
+                    0,
+                )
+            };
+            output
+        })
+        .join()
+        .unwrap();
+        assert_eq!(output, expected);
+
+        unsafe { CodespanDiagnostic::codespan_delete_diagnostic(diagnostic) }
+    }
+
+    #[test]
+    fn staleness_test() {
+        let (simple_map, file_id) = fizz_buzz_map();
+        let source = simple_map.source(file_id).unwrap();
+        let name = "src/FizzBuzz.fun";
+        let header = "-- generated\n";
+        let edit = CodespanTextEdit {
+            start: 0,
+            end: 0,
+            text: header.as_ptr(),
+            text_length: header.len(),
+        };
+
+        let write = |diagnostic, src_map| {
+            let mut output = String::new();
+            unsafe {
+                CodespanDiagnostic::codespan_write_diagnostic(
+                    &mut output as *mut _ as *mut libc::c_void,
+                    diagnostic,
+                    src_map,
+                    0,
+                )
+            };
+            output
+        };
+
+        unsafe {
+            let src_map = CodespanSourceMap::codespan_new_owned_source_map();
+            let file_id = CodespanSourceMap::codespan_source_map_add_file(
+                src_map,
+                name.as_ptr(),
+                name.len(),
+                source.as_ptr(),
+                source.len(),
+            );
+            let no_snippet = new_fizz_buzz_diagnostic(file_id);
+            let noted = new_fizz_buzz_diagnostic(file_id);
+            let original = write(no_snippet, src_map);
+
+            let status = CodespanDiagnostic::codespan_diagnostic_record_sources(
+                no_snippet,
+                src_map,
+                STALE_MODE_NO_SNIPPET,
+            );
+            assert_eq!(status, STATUS_OK);
+            CodespanDiagnostic::codespan_diagnostic_record_sources(noted, src_map, STALE_MODE_NOTE);
+            assert!(!CodespanDiagnostic::codespan_diagnostic_is_stale(
+                no_snippet, src_map
+            ));
+            assert_eq!(write(no_snippet, src_map), original);
+
+            CodespanSourceMap::codespan_source_map_apply_edits(src_map, file_id, &edit, 1);
+            assert!(CodespanDiagnostic::codespan_diagnostic_is_stale(
+                no_snippet, src_map
+            ));
+            assert_eq!(
+                write(no_snippet, src_map),
+                unindent(
+                    r#"
+                    error[E0308]: `case` clauses have incompatible types
+                      ┌─ src/FizzBuzz.fun:8:12: expected `String`, found `Nat`
+                      ┌─ src/FizzBuzz.fun:5:12: this is found to be of type `String`
+                      = expected type `String`
+                      = source changed since this diagnostic was produced
+                    "#
+                )
+            );
+
+            let output = write(noted, src_map);
+            assert!(output.contains("  ┌─ src/FizzBuzz.fun:8:17\n"));
+            assert!(output.ends_with("= source changed since this diagnostic was produced\n\n"));
+
+            // Rebasing moves the recorded ranges along with the labels, so
+            // code that only moved is not stale.
+            CodespanDiagnostic::codespan_diagnostic_rebase(
+                no_snippet,
+                file_id,
+                &edit,
+                1,
+                REBASE_MODE_FLAG,
+                ptr::null_mut(),
+            );
+            assert!(!CodespanDiagnostic::codespan_diagnostic_is_stale(
+                no_snippet, src_map
+            ));
+
+            // Code that was edited is, and the recorded locations are still
+            // found for the moved labels.
+            let replacement = "Int";
+            let overlapping = CodespanTextEdit {
+                start: 176,
+                end: 179,
+                text: replacement.as_ptr(),
+                text_length: replacement.len(),
+            };
+            CodespanSourceMap::codespan_source_map_apply_edits(src_map, file_id, &overlapping, 1);
+            CodespanDiagnostic::codespan_diagnostic_rebase(
+                no_snippet,
+                file_id,
+                &overlapping,
+                1,
+                REBASE_MODE_FLAG,
+                ptr::null_mut(),
+            );
+            assert!(CodespanDiagnostic::codespan_diagnostic_is_stale(
+                no_snippet, src_map
+            ));
+            let output = write(no_snippet, src_map);
+            assert!(output.contains("  ┌─ src/FizzBuzz.fun:8:12: expected `String`, found `Nat`\n"));
+            assert!(output.contains("  ┌─ src/FizzBuzz.fun:5:12: this is found to be of type"));
+
+            CodespanDiagnostic::codespan_delete_diagnostic(no_snippet);
+            CodespanDiagnostic::codespan_delete_diagnostic(noted);
+            CodespanSourceMap::codespan_delete_source_map(src_map);
+        }
+    }
+
+    #[test]
+    fn missing_source_test() {
+        unsafe extern "C" fn missing_source_code(
+            _user_data: *mut libc::c_void,
+            _file_id: FileId,
+            _source_code_length: *mut libc::size_t,
+        ) -> *const u8 {
+            ptr::null()
+        }
+
+        let (mut simple_map, file_id) = fizz_buzz_map();
+        let src_map = new_source_map(&mut simple_map);
+        let missing_map = unsafe {
+            CodespanSourceMap::codespan_new_source_map(
+                &mut simple_map as *mut _ as *mut libc::c_void,
+                Some(file_name),
+                Some(missing_source_code),
+                Some(line_index),
+                Some(line_range),
+                None,
+                None,
+            )
+        };
+        let diagnostic = new_fizz_buzz_diagnostic(file_id);
+
+        let write = |diagnostic| {
+            let mut output = String::new();
+            unsafe {
+                CodespanDiagnostic::codespan_write_diagnostic(
+                    &mut output as *mut _ as *mut libc::c_void,
+                    diagnostic,
+                    missing_map,
+                    0,
+                )
+            };
+            output
+        };
+
+        assert_eq!(
+            write(diagnostic),
+            unindent(
+                r#"
+                error[E0308]: `case` clauses have incompatible types
+                  ┌─ src/FizzBuzz.fun: expected `String`, found `Nat`
+                  ┌─ src/FizzBuzz.fun: this is found to be of type `String`
+                  = expected type `String`
+                  = source of `src/FizzBuzz.fun` is unavailable: file missing
+                "#
+            )
+        );
+
+        // Line formats fall back to the file name.
+        let gnu = new_fizz_buzz_diagnostic(file_id);
+        unsafe {
+            CodespanDiagnostic::codespan_diagnostic_set_format(
+                gnu,
+                OUTPUT_FORMAT_GNU,
+                FORMAT_OPTION_SECONDARY_NOTES | FORMAT_OPTION_RANGES,
+            )
+        };
+        assert_eq!(
+            write(gnu),
+            unindent(
+                r#"
+                src/FizzBuzz.fun: error: `case` clauses have incompatible types [E0308]
+                src/FizzBuzz.fun: note: this is found to be of type `String`
+                "#
+            )
+        );
+
+        // Recorded locations are shown when the source is gone.
+        unsafe {
+            CodespanDiagnostic::codespan_diagnostic_record_sources(
+                diagnostic,
+                src_map,
+                STALE_MODE_NOTE,
+            )
+        };
+        assert!(write(diagnostic).contains("  ┌─ src/FizzBuzz.fun:8:12: expected `String`"));
+        unsafe {
+            CodespanDiagnostic::codespan_diagnostic_record_sources(gnu, src_map, STALE_MODE_NOTE)
+        };
+        assert_eq!(
+            write(gnu),
+            unindent(
+                r#"
+                src/FizzBuzz.fun:8:12: error: `case` clauses have incompatible types [E0308]
+                src/FizzBuzz.fun:5:12: note: this is found to be of type `String`
+                "#
+            )
+        );
+
+        // JUnit failure bodies use the same fallback as the terminal format.
+        let mut junit = String::new();
+        unsafe {
+            CodespanDiagnostic::codespan_write_junit(
+                &mut junit as *mut _ as *mut libc::c_void,
+                &(diagnostic as *const _),
+                1,
+                missing_map,
+                ptr::null(),
+                0,
+                Some(collect_callback),
+            )
+        };
+        assert!(
+            junit.contains("&#10;  = source of `src/FizzBuzz.fun` is unavailable: file missing")
+        );
+        unsafe {
+            CodespanDiagnostic::codespan_diagnostic_set_format(gnu, OUTPUT_FORMAT_GITHUB_ACTIONS, 0)
+        };
+        assert_eq!(
+            write(gnu),
+            "::error file=src/FizzBuzz.fun,line=8,col=12,title=E0308::\
+             `case` clauses have incompatible types%0Aexpected type `String`\n"
+        );
+        unsafe {
+            CodespanDiagnostic::codespan_diagnostic_set_format(gnu, OUTPUT_FORMAT_RUSTC_JSON, 0)
+        };
+        let message: serde_json::Value = serde_json::from_str(&write(gnu)).unwrap();
+        assert_eq!(message["spans"][0]["line_start"], 8);
+        assert_eq!(message["spans"][1]["column_start"], 12);
+
+        unsafe {
+            CodespanDiagnostic::codespan_delete_diagnostic(diagnostic);
+            CodespanDiagnostic::codespan_delete_diagnostic(gnu);
+            CodespanSourceMap::codespan_delete_source_map(missing_map);
+            CodespanSourceMap::codespan_delete_source_map(src_map)
+        }
+    }
 }
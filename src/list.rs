@@ -0,0 +1,169 @@
+use crate::color::{self, ColorMode};
+use crate::diagnostic::{CodespanDiagnostic, WriterCallback};
+use crate::output;
+use crate::source_map::CodespanSourceMap;
+use codespan_reporting::files::Files;
+use codespan_reporting::term::termcolor;
+use std::cmp::{Ordering, Reverse};
+use std::io::Write;
+use std::ptr;
+
+pub type SortOrder = libc::size_t;
+pub const SORT_ORDER_POSITION: SortOrder = 0;
+pub const SORT_ORDER_SEVERITY: SortOrder = 1;
+
+pub type ListOptions = libc::size_t;
+pub const LIST_OPTION_GROUP_BY_FILE: ListOptions = 1 << 0;
+pub const LIST_OPTION_FILE_HEADERS: ListOptions = 1 << 1;
+
+pub struct CodespanDiagnosticList {
+    pub(crate) diagnostics: Vec<CodespanDiagnostic>,
+}
+
+impl CodespanDiagnosticList {
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_new_diagnostic_list() -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            diagnostics: Vec::new(),
+        }))
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_delete_diagnostic_list(list: *mut Self) {
+        if !list.is_null() {
+            unsafe { drop(Box::from_raw(list)) }
+        }
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_diagnostic_list_push(
+        list: *mut Self,
+        diagnostic: *mut CodespanDiagnostic,
+    ) {
+        if !list.is_null() && !diagnostic.is_null() {
+            let list = unsafe { &mut *list };
+            list.diagnostics.push(*unsafe { Box::from_raw(diagnostic) });
+        }
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_diagnostic_list_length(list: *const Self) -> libc::size_t {
+        if list.is_null() {
+            0
+        } else {
+            unsafe { &*list }.diagnostics.len()
+        }
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_diagnostic_list_get(
+        list: *const Self,
+        index: libc::size_t,
+    ) -> *const CodespanDiagnostic {
+        if list.is_null() {
+            return ptr::null();
+        }
+
+        unsafe { &*list }
+            .diagnostics
+            .get(index)
+            .map_or(ptr::null(), |diagnostic| diagnostic)
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_diagnostic_list_sort(
+        list: *mut Self,
+        source_map: *const CodespanSourceMap,
+        order: SortOrder,
+    ) {
+        if !list.is_null() && !source_map.is_null() {
+            let list = unsafe { &mut *list };
+            let source_map = unsafe { &*source_map };
+
+            list.diagnostics.sort_by(|a, b| match order {
+                SORT_ORDER_SEVERITY => Reverse(a.diagnostic.severity)
+                    .cmp(&Reverse(b.diagnostic.severity))
+                    .then_with(|| compare_positions(source_map, a, b)),
+                _ => compare_positions(source_map, a, b),
+            });
+        }
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_diagnostic_list_write(
+        user_data: *mut libc::c_void,
+        list: *const Self,
+        source_map: *const CodespanSourceMap,
+        color: ColorMode,
+        options: ListOptions,
+        writer: Option<WriterCallback>,
+    ) {
+        if list.is_null() || source_map.is_null() {
+            return;
+        }
+
+        let list = unsafe { &*list };
+        let source_map = unsafe { &*source_map };
+
+        let mut buffer_stream = if color::enabled(color, None) {
+            termcolor::Buffer::ansi()
+        } else {
+            termcolor::Buffer::no_color()
+        };
+
+        let mut diagnostics: Vec<&CodespanDiagnostic> = list.diagnostics.iter().collect();
+        if options & (LIST_OPTION_GROUP_BY_FILE | LIST_OPTION_FILE_HEADERS) != 0 {
+            diagnostics.sort_by(|a, b| compare_files(source_map, a, b));
+        }
+
+        let mut current_file = None;
+        for diagnostic in diagnostics {
+            let file_name = file_name(source_map, diagnostic);
+            if options & LIST_OPTION_FILE_HEADERS != 0 && file_name != current_file {
+                if let Some(file_name) = file_name {
+                    writeln!(buffer_stream, "==> {file_name} <==").unwrap();
+                }
+                current_file = file_name;
+            }
+            // Nothing is written if any diagnostic fails to render.
+            if diagnostic.render(&mut buffer_stream, source_map).is_err() {
+                return;
+            }
+        }
+
+        let utf8_output = buffer_stream.into_inner();
+        unsafe { (writer.unwrap())(user_data, utf8_output.as_ptr(), utf8_output.len()) };
+    }
+}
+
+fn file_name<'a>(
+    source_map: &'a CodespanSourceMap,
+    diagnostic: &CodespanDiagnostic,
+) -> Option<&'a str> {
+    let label = output::primary_label(&diagnostic.diagnostic)?;
+    source_map.name(label.file_id).ok()
+}
+
+// Diagnostics without a file are sorted after all others.
+fn compare_files(
+    source_map: &CodespanSourceMap,
+    a: &CodespanDiagnostic,
+    b: &CodespanDiagnostic,
+) -> Ordering {
+    match (file_name(source_map, a), file_name(source_map, b)) {
+        (Some(a), Some(b)) => a.cmp(b),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    }
+}
+
+fn compare_positions(
+    source_map: &CodespanSourceMap,
+    a: &CodespanDiagnostic,
+    b: &CodespanDiagnostic,
+) -> Ordering {
+    let range = |diagnostic: &CodespanDiagnostic| {
+        output::primary_label(&diagnostic.diagnostic)
+            .map(|label| (label.range.start, label.range.end))
+    };
+    compare_files(source_map, a, b).then_with(|| range(a).cmp(&range(b)))
+}
@@ -0,0 +1,130 @@
+use crate::diagnostic::CodespanDiagnostic;
+use crate::source_map::CodespanSourceMap;
+use crate::FileId;
+use codespan_reporting as csr;
+use codespan_reporting::diagnostic as csr_diag;
+use codespan_reporting::files::Files;
+use std::slice;
+
+pub mod checkstyle;
+pub mod github;
+pub mod gitlab;
+pub mod junit;
+pub mod line;
+pub mod lsp;
+pub mod plain;
+pub mod rustc;
+pub mod sarif;
+
+pub(crate) struct Span {
+    pub(crate) start: csr::files::Location,
+    pub(crate) end: csr::files::Location,
+}
+
+pub(crate) unsafe fn diagnostics_from_raw<'a>(
+    diagnostics: *const *const CodespanDiagnostic,
+    diagnostics_length: libc::size_t,
+) -> Vec<&'a CodespanDiagnostic> {
+    if diagnostics.is_null() {
+        Vec::new()
+    } else {
+        unsafe { slice::from_raw_parts(diagnostics, diagnostics_length) }
+            .iter()
+            .filter(|diagnostic| !diagnostic.is_null())
+            .map(|diagnostic| unsafe { &**diagnostic })
+            .collect()
+    }
+}
+
+pub(crate) fn primary_label(
+    diagnostic: &csr_diag::Diagnostic<FileId>,
+) -> Option<&csr_diag::Label<FileId>> {
+    diagnostic
+        .labels
+        .iter()
+        .find(|label| label.style == csr_diag::LabelStyle::Primary)
+}
+
+pub(crate) fn secondary_labels(
+    diagnostic: &csr_diag::Diagnostic<FileId>,
+) -> impl Iterator<Item = &csr_diag::Label<FileId>> {
+    diagnostic
+        .labels
+        .iter()
+        .filter(|label| label.style == csr_diag::LabelStyle::Secondary)
+}
+
+pub(crate) fn label_span(
+    source_map: &CodespanSourceMap,
+    label: &csr_diag::Label<FileId>,
+) -> Result<Span, csr::files::Error> {
+    Ok(Span {
+        start: source_map.location(label.file_id, label.range.start)?,
+        end: source_map.location(label.file_id, label.range.end)?,
+    })
+}
+
+pub(crate) fn severity_name(severity: csr_diag::Severity) -> &'static str {
+    match severity {
+        csr_diag::Severity::Help => "help",
+        csr_diag::Severity::Note => "note",
+        csr_diag::Severity::Warning => "warning",
+        csr_diag::Severity::Error => "error",
+        csr_diag::Severity::Bug => "bug",
+    }
+}
+
+pub(crate) fn message_with_notes(diagnostic: &csr_diag::Diagnostic<FileId>) -> String {
+    let mut message = diagnostic.message.clone();
+    for note in &diagnostic.notes {
+        message.push('\n');
+        message.push_str(note);
+    }
+    message
+}
+
+pub(crate) fn file_uri(name: &str) -> String {
+    if name.contains("://") {
+        return name.to_owned();
+    }
+
+    let path = name.replace('\\', "/");
+    let is_drive_path = path.as_bytes().get(1) == Some(&b':');
+    let mut uri = if path.starts_with('/') {
+        "file://".to_owned()
+    } else if is_drive_path {
+        "file:///".to_owned()
+    } else {
+        String::new()
+    };
+
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            b':' if is_drive_path => uri.push(':'),
+            _ => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    uri
+}
+
+pub(crate) fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' => escaped.push_str("&#10;"),
+            '\t' | '\r' => escaped.push(c),
+            // Other control characters are not allowed in XML 1.0 documents.
+            c if c < ' ' => escaped.push(char::REPLACEMENT_CHARACTER),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
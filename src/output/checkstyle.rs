@@ -0,0 +1,89 @@
+use crate::diagnostic::{CodespanDiagnostic, WriterCallback};
+use crate::output::{self, escape_xml};
+use crate::source_map::CodespanSourceMap;
+use codespan_reporting::diagnostic as csr_diag;
+use codespan_reporting::files::Files;
+use std::fmt::Write;
+
+impl CodespanDiagnostic {
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_write_checkstyle(
+        user_data: *mut libc::c_void,
+        diagnostics: *const *const Self,
+        diagnostics_length: libc::size_t,
+        source_map: *const CodespanSourceMap,
+        writer: Option<WriterCallback>,
+    ) {
+        if !source_map.is_null() {
+            let diagnostics =
+                unsafe { output::diagnostics_from_raw(diagnostics, diagnostics_length) };
+            let source_map = unsafe { &*source_map };
+
+            let utf8_output = checkstyle_report(&diagnostics, source_map);
+            unsafe { (writer.unwrap())(user_data, utf8_output.as_ptr(), utf8_output.len()) };
+        }
+    }
+}
+
+pub(crate) fn checkstyle_report(
+    diagnostics: &[&CodespanDiagnostic],
+    source_map: &CodespanSourceMap,
+) -> String {
+    let mut files: Vec<(&str, Vec<String>)> = Vec::new();
+
+    for diagnostic in diagnostics {
+        let diagnostic = &diagnostic.diagnostic;
+        let Some(label) = output::primary_label(diagnostic) else {
+            continue;
+        };
+        let Ok(name) = source_map.name(label.file_id) else {
+            continue;
+        };
+
+        let mut error = String::from("<error");
+        if let Ok(location) = source_map.location(label.file_id, label.range.start) {
+            write!(
+                error,
+                " line=\"{}\" column=\"{}\"",
+                location.line_number, location.column_number
+            )
+            .unwrap();
+        }
+        write!(
+            error,
+            " severity=\"{}\" message=\"{}\"",
+            severity(diagnostic.severity),
+            escape_xml(&output::message_with_notes(diagnostic))
+        )
+        .unwrap();
+        if let Some(code) = &diagnostic.code {
+            write!(error, " source=\"{}\"", escape_xml(code)).unwrap();
+        }
+        error.push_str("/>");
+
+        match files.iter_mut().find(|(file_name, _)| *file_name == name) {
+            Some((_, errors)) => errors.push(error),
+            None => files.push((name, vec![error])),
+        }
+    }
+
+    let mut report =
+        String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<checkstyle version=\"4.3\">\n");
+    for (name, errors) in files {
+        writeln!(report, "  <file name=\"{}\">", escape_xml(name)).unwrap();
+        for error in errors {
+            writeln!(report, "    {error}").unwrap();
+        }
+        report.push_str("  </file>\n");
+    }
+    report.push_str("</checkstyle>\n");
+    report
+}
+
+fn severity(severity: csr_diag::Severity) -> &'static str {
+    match severity {
+        csr_diag::Severity::Bug | csr_diag::Severity::Error => "error",
+        csr_diag::Severity::Warning => "warning",
+        csr_diag::Severity::Note | csr_diag::Severity::Help => "info",
+    }
+}
@@ -0,0 +1,117 @@
+use crate::diagnostic::{CodespanDiagnostic, FORMAT_OPTION_GITHUB_GROUP};
+use crate::output::plain::{self, Locus};
+use crate::output::{self, Span};
+use crate::source_map::CodespanSourceMap;
+use codespan_reporting as csr;
+use codespan_reporting::diagnostic as csr_diag;
+use codespan_reporting::files::Files;
+use codespan_reporting::term::termcolor::WriteColor;
+use std::io;
+
+pub(crate) fn emit<W: WriteColor>(
+    writer: &mut W,
+    diagnostic: &CodespanDiagnostic,
+    source_map: &CodespanSourceMap,
+) -> Result<(), csr::files::Error> {
+    let inner = &diagnostic.diagnostic;
+
+    let mut properties = Vec::new();
+    if let Some(label) = output::primary_label(inner) {
+        properties.push(format!(
+            "file={}",
+            escape_property(source_map.name(label.file_id)?)
+        ));
+        let Span { start, end } = output::label_span(source_map, label)?;
+        properties.push(format!("line={}", start.line_number));
+        properties.push(format!("col={}", start.column_number));
+        properties.push(format!("endLine={}", end.line_number));
+        properties.push(format!("endColumn={}", end.column_number));
+    }
+    write_command(writer, diagnostic, properties)?;
+
+    if diagnostic.format_options & FORMAT_OPTION_GITHUB_GROUP != 0 {
+        let config = csr::term::Config {
+            display_style: csr::term::DisplayStyle::Rich,
+            ..diagnostic.config.clone()
+        };
+        writeln!(writer, "::group::{}", escape_data(&inner.message))?;
+        csr::term::emit_to_write_style(writer, &config, source_map, inner)?;
+        writeln!(writer, "::endgroup::")?;
+    }
+    Ok(())
+}
+
+// Used when a file is unavailable, with the locations from `plain::loci`.
+// The group lists the labels instead of showing snippets.
+pub(crate) fn emit_unavailable<W: WriteColor>(
+    writer: &mut W,
+    diagnostic: &CodespanDiagnostic,
+    loci: &[Locus],
+    notes: &[String],
+) -> io::Result<()> {
+    let inner = &diagnostic.diagnostic;
+
+    let mut properties = Vec::new();
+    let primary = inner
+        .labels
+        .iter()
+        .position(|label| label.style == csr_diag::LabelStyle::Primary);
+    if let Some(Locus { name, location }) = primary.map(|index| &loci[index]) {
+        if let Some(name) = name {
+            properties.push(format!("file={}", escape_property(name)));
+        }
+        if let Some(location) = location {
+            properties.push(format!("line={}", location.line_number));
+            properties.push(format!("col={}", location.column_number));
+        }
+    }
+    write_command(writer, diagnostic, properties)?;
+
+    if diagnostic.format_options & FORMAT_OPTION_GITHUB_GROUP != 0 {
+        writeln!(writer, "::group::{}", escape_data(&inner.message))?;
+        plain::emit_unavailable(writer, diagnostic, loci, notes)?;
+        writeln!(writer, "::endgroup::")?;
+    }
+    Ok(())
+}
+
+fn write_command<W: WriteColor>(
+    writer: &mut W,
+    diagnostic: &CodespanDiagnostic,
+    mut properties: Vec<String>,
+) -> io::Result<()> {
+    let inner = &diagnostic.diagnostic;
+    if let Some(code) = &inner.code {
+        properties.push(format!("title={}", escape_property(code)));
+    }
+
+    write!(writer, "::{}", command(inner.severity))?;
+    if !properties.is_empty() {
+        write!(writer, " {}", properties.join(","))?;
+    }
+    writeln!(
+        writer,
+        "::{}",
+        escape_data(&output::message_with_notes(inner))
+    )
+}
+
+fn command(severity: csr_diag::Severity) -> &'static str {
+    match severity {
+        csr_diag::Severity::Bug | csr_diag::Severity::Error => "error",
+        csr_diag::Severity::Warning => "warning",
+        csr_diag::Severity::Note | csr_diag::Severity::Help => "notice",
+    }
+}
+
+fn escape_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(property: &str) -> String {
+    escape_data(property)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
@@ -0,0 +1,88 @@
+use crate::diagnostic::{CodespanDiagnostic, WriterCallback};
+use crate::fingerprint::{self, Fingerprint, Fnv1a};
+use crate::output;
+use crate::source_map::CodespanSourceMap;
+use codespan_reporting::diagnostic as csr_diag;
+use codespan_reporting::files::Files;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+impl CodespanDiagnostic {
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_write_gitlab_code_quality(
+        user_data: *mut libc::c_void,
+        diagnostics: *const *const Self,
+        diagnostics_length: libc::size_t,
+        source_map: *const CodespanSourceMap,
+        writer: Option<WriterCallback>,
+    ) {
+        if !source_map.is_null() {
+            let diagnostics =
+                unsafe { output::diagnostics_from_raw(diagnostics, diagnostics_length) };
+            let source_map = unsafe { &*source_map };
+
+            let utf8_output =
+                serde_json::to_vec_pretty(&code_quality_report(&diagnostics, source_map)).unwrap();
+            unsafe { (writer.unwrap())(user_data, utf8_output.as_ptr(), utf8_output.len()) };
+        }
+    }
+}
+
+pub(crate) fn code_quality_report(
+    diagnostics: &[&CodespanDiagnostic],
+    source_map: &CodespanSourceMap,
+) -> Value {
+    let mut occurrences: HashMap<Fingerprint, u64> = HashMap::new();
+
+    diagnostics
+        .iter()
+        .filter_map(|diagnostic| {
+            let diagnostic = &diagnostic.diagnostic;
+            let label = output::primary_label(diagnostic)?;
+            let path = source_map.name(label.file_id).ok()?;
+            let line = source_map
+                .location(label.file_id, label.range.start)
+                .ok()?
+                .line_number;
+            let check_name = diagnostic
+                .code
+                .as_deref()
+                .unwrap_or(output::severity_name(diagnostic.severity));
+
+            // GitLab uses the fingerprint to match issues between the source
+            // and target branch, so repeats of the same issue need their own.
+            let hash = fingerprint::fingerprint(diagnostic, source_map);
+            let occurrence = occurrences.entry(hash).or_default();
+            *occurrence += 1;
+            let fingerprint = if *occurrence == 1 {
+                hash
+            } else {
+                let mut hasher = Fnv1a::new();
+                hasher.write(&hash.to_le_bytes());
+                hasher.write(&occurrence.to_le_bytes());
+                hasher.finish()
+            };
+
+            Some(json!({
+                "description": output::message_with_notes(diagnostic),
+                "check_name": check_name,
+                "fingerprint": format!("{fingerprint:016x}"),
+                "severity": severity(diagnostic.severity),
+                "location": {
+                    "path": path,
+                    "lines": { "begin": line },
+                },
+            }))
+        })
+        .collect()
+}
+
+fn severity(severity: csr_diag::Severity) -> &'static str {
+    match severity {
+        csr_diag::Severity::Help => "info",
+        csr_diag::Severity::Note => "minor",
+        csr_diag::Severity::Warning => "major",
+        csr_diag::Severity::Error => "critical",
+        csr_diag::Severity::Bug => "blocker",
+    }
+}
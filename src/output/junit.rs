@@ -0,0 +1,110 @@
+use crate::diagnostic::{CodespanDiagnostic, WriterCallback};
+use crate::output::{self, escape_xml};
+use crate::source_map::CodespanSourceMap;
+use codespan_reporting::diagnostic as csr_diag;
+use codespan_reporting::files::Files;
+use codespan_reporting::term::termcolor;
+use std::fmt::Write;
+
+impl CodespanDiagnostic {
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_write_junit(
+        user_data: *mut libc::c_void,
+        diagnostics: *const *const Self,
+        diagnostics_length: libc::size_t,
+        source_map: *const CodespanSourceMap,
+        suite_name: *const u8,
+        suite_name_length: libc::size_t,
+        writer: Option<WriterCallback>,
+    ) {
+        if !source_map.is_null() {
+            let diagnostics =
+                unsafe { output::diagnostics_from_raw(diagnostics, diagnostics_length) };
+            let source_map = unsafe { &*source_map };
+            let suite_name = Self::utf8_to_string(suite_name, suite_name_length);
+
+            let utf8_output = junit_report(&diagnostics, source_map, &suite_name);
+            unsafe { (writer.unwrap())(user_data, utf8_output.as_ptr(), utf8_output.len()) };
+        }
+    }
+}
+
+const NO_FILE_TESTCASE: &str = "(no file)";
+
+pub(crate) fn junit_report(
+    diagnostics: &[&CodespanDiagnostic],
+    source_map: &CodespanSourceMap,
+    suite_name: &str,
+) -> String {
+    let mut testcases: Vec<(&str, Vec<String>)> = Vec::new();
+
+    for diagnostic in diagnostics {
+        let name = output::primary_label(&diagnostic.diagnostic)
+            .and_then(|label| source_map.name(label.file_id).ok())
+            .unwrap_or(NO_FILE_TESTCASE);
+        let failures = match testcases.iter_mut().find(|(testcase, _)| *testcase == name) {
+            Some((_, failures)) => failures,
+            None => {
+                testcases.push((name, Vec::new()));
+                &mut testcases.last_mut().unwrap().1
+            }
+        };
+
+        if matches!(
+            diagnostic.diagnostic.severity,
+            csr_diag::Severity::Error | csr_diag::Severity::Bug
+        ) {
+            failures.push(failure(diagnostic, source_map));
+        }
+    }
+
+    let failure_count: usize = testcases.iter().map(|(_, failures)| failures.len()).sum();
+    let suite_attributes = format!(
+        "name=\"{}\" tests=\"{}\" failures=\"{}\"",
+        escape_xml(suite_name),
+        testcases.len(),
+        failure_count
+    );
+
+    let mut report = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    writeln!(report, "<testsuites {suite_attributes}>").unwrap();
+    writeln!(report, "  <testsuite {suite_attributes}>").unwrap();
+    for (name, failures) in testcases {
+        let testcase = format!(
+            "<testcase name=\"{}\" classname=\"{}\"",
+            escape_xml(name),
+            escape_xml(suite_name)
+        );
+        if failures.is_empty() {
+            writeln!(report, "    {testcase}/>").unwrap();
+        } else {
+            writeln!(report, "    {testcase}>").unwrap();
+            for failure in failures {
+                writeln!(report, "      {failure}").unwrap();
+            }
+            report.push_str("    </testcase>\n");
+        }
+    }
+    report.push_str("  </testsuite>\n</testsuites>\n");
+    report
+}
+
+fn failure(diagnostic: &CodespanDiagnostic, source_map: &CodespanSourceMap) -> String {
+    // The failure body is always the terminal rendering, whatever format the
+    // diagnostic is set to.
+    let mut buffer = termcolor::Buffer::no_color();
+    let rendered = match diagnostic.render_term(&mut buffer, source_map) {
+        Ok(()) => String::from_utf8_lossy(buffer.as_slice()).into_owned(),
+        Err(_) => output::message_with_notes(&diagnostic.diagnostic),
+    };
+
+    let mut failure = format!(
+        "<failure message=\"{}\"",
+        escape_xml(&diagnostic.diagnostic.message)
+    );
+    if let Some(code) = &diagnostic.diagnostic.code {
+        write!(failure, " type=\"{}\"", escape_xml(code)).unwrap();
+    }
+    write!(failure, ">{}</failure>", escape_xml(&rendered)).unwrap();
+    failure
+}
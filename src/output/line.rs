@@ -0,0 +1,119 @@
+use crate::diagnostic::{
+    CodespanDiagnostic, FORMAT_OPTION_RANGES, FORMAT_OPTION_SECONDARY_NOTES, OUTPUT_FORMAT_MSVC,
+};
+use crate::output::plain::Locus;
+use crate::output::{self, Span};
+use crate::source_map::CodespanSourceMap;
+use crate::FileId;
+use codespan_reporting as csr;
+use codespan_reporting::diagnostic as csr_diag;
+use codespan_reporting::files::Files;
+use codespan_reporting::term::termcolor::WriteColor;
+
+pub(crate) fn emit<W: WriteColor>(
+    writer: &mut W,
+    diagnostic: &CodespanDiagnostic,
+    source_map: &CodespanSourceMap,
+) -> Result<(), csr::files::Error> {
+    let ranges = diagnostic.format_options & FORMAT_OPTION_RANGES != 0;
+    write_lines(writer, diagnostic, |_, label| {
+        let name = source_map.name(label.file_id)?;
+        let Span { start, end } = output::label_span(source_map, label)?;
+        Ok(Some(locus(
+            diagnostic,
+            name,
+            Some(start),
+            ranges.then_some(end),
+        )))
+    })
+}
+
+// Used when a file is unavailable, with the locations from `plain::loci`.
+// Only the start of a label is known then, so ranges are left out.
+pub(crate) fn emit_unavailable<W: WriteColor>(
+    writer: &mut W,
+    diagnostic: &CodespanDiagnostic,
+    loci: &[Locus],
+) -> Result<(), csr::files::Error> {
+    write_lines(writer, diagnostic, |index, _| {
+        let locus = &loci[index];
+        Ok(locus
+            .name
+            .as_deref()
+            .map(|name| self::locus(diagnostic, name, locus.location, None)))
+    })
+}
+
+// `locus` returns the prefix of a label's line, if its file name is known.
+fn write_lines<W: WriteColor>(
+    writer: &mut W,
+    diagnostic: &CodespanDiagnostic,
+    locus: impl Fn(usize, &csr_diag::Label<FileId>) -> Result<Option<String>, csr::files::Error>,
+) -> Result<(), csr::files::Error> {
+    let inner = &diagnostic.diagnostic;
+    let msvc = diagnostic.format == OUTPUT_FORMAT_MSVC;
+
+    let severity = match inner.severity {
+        csr_diag::Severity::Bug => "error",
+        severity => output::severity_name(severity),
+    };
+    let code = inner.code.as_deref().filter(|code| !code.is_empty());
+
+    let labels = inner.labels.iter().enumerate();
+    if let Some((index, label)) = labels
+        .clone()
+        .find(|(_, label)| label.style == csr_diag::LabelStyle::Primary)
+        && let Some(locus) = locus(index, label)?
+    {
+        write!(writer, "{locus} ")?;
+    }
+    let message = single_line(&inner.message);
+    match code {
+        Some(code) if msvc => writeln!(writer, "{severity} {code}: {message}")?,
+        Some(code) => writeln!(writer, "{severity}: {message} [{code}]")?,
+        None => writeln!(writer, "{severity}: {message}")?,
+    }
+
+    if diagnostic.format_options & FORMAT_OPTION_SECONDARY_NOTES != 0 {
+        for (index, label) in
+            labels.filter(|(_, label)| label.style == csr_diag::LabelStyle::Secondary)
+        {
+            if let Some(locus) = locus(index, label)? {
+                write!(writer, "{locus} ")?;
+            }
+            writeln!(writer, "note: {}", single_line(&label.message))?;
+        }
+    }
+    Ok(())
+}
+
+fn locus(
+    diagnostic: &CodespanDiagnostic,
+    name: &str,
+    start: Option<csr::files::Location>,
+    end: Option<csr::files::Location>,
+) -> String {
+    let msvc = diagnostic.format == OUTPUT_FORMAT_MSVC;
+    match (msvc, start, end) {
+        (true, Some(start), Some(end)) => format!(
+            "{name}({},{},{},{}):",
+            start.line_number, start.column_number, end.line_number, end.column_number
+        ),
+        (true, Some(start), None) => {
+            format!("{name}({},{}):", start.line_number, start.column_number)
+        }
+        (false, Some(start), Some(end)) => format!(
+            "{name}:{}.{}-{}.{}:",
+            start.line_number, start.column_number, end.line_number, end.column_number
+        ),
+        (false, Some(start), None) => {
+            format!("{name}:{}:{}:", start.line_number, start.column_number)
+        }
+        (_, None, _) => format!("{name}:"),
+    }
+}
+
+// Tools parsing these formats expect exactly one diagnostic per line.
+fn single_line(message: &str) -> String {
+    message.lines().collect::<Vec<_>>().join(" ")
+}
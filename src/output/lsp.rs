@@ -0,0 +1,135 @@
+use crate::diagnostic::{CodespanDiagnostic, WriterCallback};
+use crate::output;
+use crate::source_map::CodespanSourceMap;
+use crate::{ByteIndex, FileId};
+use codespan_reporting as csr;
+use codespan_reporting::diagnostic as csr_diag;
+use codespan_reporting::files::Files;
+use serde_json::{json, Map, Value};
+
+impl CodespanDiagnostic {
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_write_lsp_diagnostics(
+        user_data: *mut libc::c_void,
+        diagnostic: *const Self,
+        source_map: *const CodespanSourceMap,
+        source: *const u8,
+        source_length: libc::size_t,
+        writer: Option<WriterCallback>,
+    ) {
+        if !diagnostic.is_null() && !source_map.is_null() {
+            let diagnostic = unsafe { &*diagnostic };
+            let source_map = unsafe { &*source_map };
+            let source = (!source.is_null()).then(|| Self::utf8_to_string(source, source_length));
+
+            let utf8_output =
+                serde_json::to_vec(&publish_params(diagnostic, source_map, source.as_deref()))
+                    .unwrap();
+            unsafe { (writer.unwrap())(user_data, utf8_output.as_ptr(), utf8_output.len()) };
+        }
+    }
+}
+
+pub(crate) fn publish_params(
+    diagnostic: &CodespanDiagnostic,
+    source_map: &CodespanSourceMap,
+    source: Option<&str>,
+) -> Value {
+    let diagnostic = &diagnostic.diagnostic;
+    let mut files: Vec<(String, Vec<Value>)> = Vec::new();
+
+    let related_information: Vec<Value> = output::secondary_labels(diagnostic)
+        .filter_map(|label| {
+            Some(json!({
+                "location": {
+                    "uri": output::file_uri(source_map.name(label.file_id).ok()?),
+                    "range": range(source_map, label).ok()?,
+                },
+                "message": label.message,
+            }))
+        })
+        .collect();
+
+    for label in diagnostic
+        .labels
+        .iter()
+        .filter(|label| label.style == csr_diag::LabelStyle::Primary)
+    {
+        let (Ok(name), Ok(range)) = (source_map.name(label.file_id), range(source_map, label))
+        else {
+            continue;
+        };
+
+        let mut lsp_diagnostic = Map::new();
+        lsp_diagnostic.insert("range".to_owned(), range);
+        lsp_diagnostic.insert("severity".to_owned(), json!(severity(diagnostic.severity)));
+        if let Some(code) = &diagnostic.code {
+            lsp_diagnostic.insert("code".to_owned(), json!(code));
+        }
+        if let Some(source) = source {
+            lsp_diagnostic.insert("source".to_owned(), json!(source));
+        }
+        lsp_diagnostic.insert(
+            "message".to_owned(),
+            json!(output::message_with_notes(diagnostic)),
+        );
+        if !related_information.is_empty() {
+            lsp_diagnostic.insert(
+                "relatedInformation".to_owned(),
+                Value::Array(related_information.clone()),
+            );
+        }
+
+        let uri = output::file_uri(name);
+        match files.iter_mut().find(|(file_uri, _)| *file_uri == uri) {
+            Some((_, diagnostics)) => diagnostics.push(Value::Object(lsp_diagnostic)),
+            None => files.push((uri, vec![Value::Object(lsp_diagnostic)])),
+        }
+    }
+
+    files
+        .into_iter()
+        .map(|(uri, diagnostics)| json!({ "uri": uri, "diagnostics": diagnostics }))
+        .collect()
+}
+
+fn severity(severity: csr_diag::Severity) -> u8 {
+    match severity {
+        csr_diag::Severity::Bug | csr_diag::Severity::Error => 1,
+        csr_diag::Severity::Warning => 2,
+        csr_diag::Severity::Note => 3,
+        csr_diag::Severity::Help => 4,
+    }
+}
+
+fn range(
+    source_map: &CodespanSourceMap,
+    label: &csr_diag::Label<FileId>,
+) -> Result<Value, csr::files::Error> {
+    Ok(json!({
+        "start": position(source_map, label.file_id, label.range.start)?,
+        "end": position(source_map, label.file_id, label.range.end)?,
+    }))
+}
+
+// LSP positions are a zero based line index and an offset into that line in
+// UTF-16 code units.
+fn position(
+    source_map: &CodespanSourceMap,
+    file_id: FileId,
+    byte_index: ByteIndex,
+) -> Result<Value, csr::files::Error> {
+    let source = source_map.source(file_id)?;
+    let line_index = source_map.line_index(file_id, byte_index)?;
+    let line_start = source_map.line_range(file_id, line_index)?.start;
+
+    let mut end = byte_index.min(source.len());
+    while !source.is_char_boundary(end) {
+        end -= 1;
+    }
+    let character: usize = source
+        .get(line_start.min(end)..end)
+        .map_or(0, |prefix| prefix.chars().map(char::len_utf16).sum());
+
+    Ok(json!({ "line": line_index, "character": character }))
+}
@@ -0,0 +1,142 @@
+use crate::diagnostic::CodespanDiagnostic;
+use crate::source_map::CodespanSourceMap;
+use crate::FileId;
+use codespan_reporting as csr;
+use codespan_reporting::diagnostic as csr_diag;
+use codespan_reporting::files::Files;
+use codespan_reporting::term::termcolor::{ColorSpec, WriteColor};
+use std::io;
+
+// Where a label points, as far as it is known.
+pub(crate) struct Locus {
+    pub(crate) name: Option<String>,
+    pub(crate) location: Option<csr::files::Location>,
+}
+
+// Renders the diagnostic like the rich format, but lists its labels with
+// their locations instead of drawing source snippets. Used when the source
+// cannot be shown as it was when the diagnostic was produced. `loci` holds
+// one entry per label, and `notes` are added after the diagnostic's own.
+pub(crate) fn emit<W: WriteColor>(
+    writer: &mut W,
+    diagnostic: &CodespanDiagnostic,
+    loci: &[Locus],
+    notes: &[&str],
+) -> io::Result<()> {
+    let inner = &diagnostic.diagnostic;
+    let styles = &diagnostic.styles;
+    let chars = &diagnostic.config.chars;
+
+    writer.set_color(styles.header(inner.severity))?;
+    write!(writer, "{}", super::severity_name(inner.severity))?;
+    if let Some(code) = inner.code.as_deref().filter(|code| !code.is_empty()) {
+        write!(writer, "[{code}]")?;
+    }
+    writer.set_color(styles.header_message())?;
+    write!(writer, ": {}", inner.message)?;
+    writer.reset()?;
+    writeln!(writer)?;
+
+    for (label, locus) in inner.labels.iter().zip(loci) {
+        writer.set_color(styles.source_border())?;
+        write!(writer, "  {}", chars.snippet_start)?;
+        writer.reset()?;
+        match (&locus.name, locus.location) {
+            (Some(name), Some(location)) => write!(
+                writer,
+                " {name}:{}:{}",
+                location.line_number, location.column_number
+            )?,
+            (Some(name), None) => write!(writer, " {name}")?,
+            (None, _) => write!(writer, " <unknown>")?,
+        }
+        if !label.message.is_empty() {
+            write!(writer, ": ")?;
+            writer.set_color(label_style(diagnostic, label))?;
+            write!(writer, "{}", label.message)?;
+            writer.reset()?;
+        }
+        writeln!(writer)?;
+    }
+
+    for note in inner
+        .notes
+        .iter()
+        .map(String::as_str)
+        .chain(notes.iter().copied())
+    {
+        writer.set_color(styles.note_bullet())?;
+        write!(writer, "  {}", chars.note_bullet)?;
+        writer.reset()?;
+        writeln!(writer, " {}", note.replace('\n', "\n    "))?;
+    }
+    Ok(())
+}
+
+fn label_style<'a>(
+    diagnostic: &'a CodespanDiagnostic,
+    label: &csr_diag::Label<FileId>,
+) -> &'a ColorSpec {
+    diagnostic
+        .styles
+        .label(diagnostic.diagnostic.severity, label.style)
+}
+
+// Describes each file the diagnostic refers to that `source_map` cannot
+// provide, so that it can be rendered without snippets instead of failing.
+pub(crate) fn unavailable_sources(
+    diagnostic: &CodespanDiagnostic,
+    source_map: &CodespanSourceMap,
+) -> Vec<String> {
+    let mut file_ids: Vec<FileId> = Vec::new();
+    let mut notes = Vec::new();
+    for label in &diagnostic.diagnostic.labels {
+        if file_ids.contains(&label.file_id) {
+            continue;
+        }
+        file_ids.push(label.file_id);
+
+        match (
+            source_map.name(label.file_id),
+            source_map.source(label.file_id),
+        ) {
+            (Ok(_), Ok(_)) => {}
+            (Ok(name), Err(error)) => {
+                notes.push(format!("source of `{name}` is unavailable: {error}"))
+            }
+            (Err(error), _) => notes.push(format!("source is unavailable: {error}")),
+        }
+    }
+    notes
+}
+
+// Locations recorded with `codespan_diagnostic_record_sources` are used for
+// files that are gone, otherwise they are looked up as far as possible.
+pub(crate) fn loci(diagnostic: &CodespanDiagnostic, source_map: &CodespanSourceMap) -> Vec<Locus> {
+    diagnostic
+        .diagnostic
+        .labels
+        .iter()
+        .enumerate()
+        .map(|(index, label)| {
+            let recorded = diagnostic
+                .sources
+                .as_ref()
+                .and_then(|sources| sources.locus(index));
+            recorded.unwrap_or_else(|| Locus {
+                name: source_map.name(label.file_id).ok().map(str::to_owned),
+                location: source_map.location(label.file_id, label.range.start).ok(),
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn emit_unavailable<W: WriteColor>(
+    writer: &mut W,
+    diagnostic: &CodespanDiagnostic,
+    loci: &[Locus],
+    notes: &[String],
+) -> io::Result<()> {
+    let notes: Vec<&str> = notes.iter().map(String::as_str).collect();
+    emit(writer, diagnostic, loci, &notes)
+}
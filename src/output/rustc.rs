@@ -0,0 +1,181 @@
+use crate::diagnostic::CodespanDiagnostic;
+use crate::output::plain::{self, Locus};
+use crate::output::{self, Span};
+use crate::source_map::CodespanSourceMap;
+use crate::FileId;
+use codespan_reporting as csr;
+use codespan_reporting::diagnostic as csr_diag;
+use codespan_reporting::files::Files;
+use codespan_reporting::term::termcolor::{self, WriteColor};
+use serde_json::{json, Value};
+use std::io;
+
+pub(crate) fn emit<W: WriteColor>(
+    writer: &mut W,
+    diagnostic: &CodespanDiagnostic,
+    source_map: &CodespanSourceMap,
+) -> Result<(), csr::files::Error> {
+    let inner = &diagnostic.diagnostic;
+
+    let mut rendered = rendered_buffer(writer);
+    let config = csr::term::Config {
+        display_style: csr::term::DisplayStyle::Rich,
+        ..diagnostic.config.clone()
+    };
+    csr::term::emit_to_write_style(&mut rendered, &config, source_map, inner)?;
+
+    let spans = inner
+        .labels
+        .iter()
+        .map(|label| span(source_map, label))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(write_message(writer, diagnostic, spans, &rendered, &[])?)
+}
+
+// Used when a file is unavailable, with the locations from `plain::loci`.
+// Only the start of a label is known then, so its span is empty, and labels
+// without a location have no span at all.
+pub(crate) fn emit_unavailable<W: WriteColor>(
+    writer: &mut W,
+    diagnostic: &CodespanDiagnostic,
+    loci: &[Locus],
+    notes: &[String],
+) -> io::Result<()> {
+    let mut rendered = rendered_buffer(writer);
+    plain::emit_unavailable(&mut rendered, diagnostic, loci, notes)?;
+
+    let spans = diagnostic
+        .diagnostic
+        .labels
+        .iter()
+        .zip(loci)
+        .filter_map(|(label, locus)| {
+            let (Some(name), Some(location)) = (&locus.name, locus.location) else {
+                return None;
+            };
+            Some(json!({
+                "file_name": name,
+                "byte_start": label.range.start,
+                "byte_end": label.range.start,
+                "line_start": location.line_number,
+                "line_end": location.line_number,
+                "column_start": location.column_number,
+                "column_end": location.column_number,
+                "is_primary": label.style == csr_diag::LabelStyle::Primary,
+                "text": [],
+                "label": (!label.message.is_empty()).then_some(&label.message),
+                "suggested_replacement": null,
+                "suggestion_applicability": null,
+                "expansion": null,
+            }))
+        })
+        .collect();
+    write_message(writer, diagnostic, spans, &rendered, notes)
+}
+
+fn rendered_buffer<W: WriteColor>(writer: &W) -> termcolor::Buffer {
+    if writer.supports_color() {
+        termcolor::Buffer::ansi()
+    } else {
+        termcolor::Buffer::no_color()
+    }
+}
+
+// `notes` are added as children after the diagnostic's own notes.
+fn write_message<W: WriteColor>(
+    writer: &mut W,
+    diagnostic: &CodespanDiagnostic,
+    spans: Vec<Value>,
+    rendered: &termcolor::Buffer,
+    notes: &[String],
+) -> io::Result<()> {
+    let inner = &diagnostic.diagnostic;
+    let children: Vec<Value> = inner
+        .notes
+        .iter()
+        .chain(notes)
+        .map(|note| {
+            json!({
+                "message": note,
+                "code": null,
+                "level": "note",
+                "spans": [],
+                "children": [],
+                "rendered": null,
+            })
+        })
+        .collect();
+
+    let message = json!({
+        "$message_type": "diagnostic",
+        "message": inner.message,
+        "code": inner.code.as_ref().map(|code| json!({ "code": code, "explanation": null })),
+        "level": level(inner.severity),
+        "spans": spans,
+        "children": children,
+        "rendered": String::from_utf8_lossy(rendered.as_slice()),
+    });
+    serde_json::to_writer(&mut *writer, &message)?;
+    writeln!(writer)
+}
+
+fn level(severity: csr_diag::Severity) -> &'static str {
+    match severity {
+        csr_diag::Severity::Bug => "error: internal compiler error",
+        csr_diag::Severity::Error => "error",
+        csr_diag::Severity::Warning => "warning",
+        csr_diag::Severity::Note => "note",
+        csr_diag::Severity::Help => "help",
+    }
+}
+
+fn span(
+    source_map: &CodespanSourceMap,
+    label: &csr_diag::Label<FileId>,
+) -> Result<Value, csr::files::Error> {
+    let Span { start, end } = output::label_span(source_map, label)?;
+    let source = source_map.source(label.file_id)?;
+    let start_line = source_map.line_index(label.file_id, label.range.start)?;
+    let end_line = source_map.line_index(label.file_id, label.range.end)?;
+
+    let text = (start_line..=end_line)
+        .map(|line_index| {
+            let line_range = source_map.line_range(label.file_id, line_index)?;
+            let line = source
+                .get(line_range)
+                .unwrap_or_default()
+                .trim_end_matches(['\n', '\r']);
+            let highlight_start = if line_index == start_line {
+                start.column_number
+            } else {
+                1
+            };
+            let highlight_end = if line_index == end_line {
+                end.column_number
+            } else {
+                line.chars().count() + 1
+            };
+            Ok(json!({
+                "text": line,
+                "highlight_start": highlight_start,
+                "highlight_end": highlight_end,
+            }))
+        })
+        .collect::<Result<Vec<_>, csr::files::Error>>()?;
+
+    Ok(json!({
+        "file_name": source_map.name(label.file_id)?,
+        "byte_start": label.range.start,
+        "byte_end": label.range.end,
+        "line_start": start.line_number,
+        "line_end": end.line_number,
+        "column_start": start.column_number,
+        "column_end": end.column_number,
+        "is_primary": label.style == csr_diag::LabelStyle::Primary,
+        "text": text,
+        "label": (!label.message.is_empty()).then_some(&label.message),
+        "suggested_replacement": null,
+        "suggestion_applicability": null,
+        "expansion": null,
+    }))
+}
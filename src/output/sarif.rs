@@ -0,0 +1,211 @@
+use crate::diagnostic::{CodespanDiagnostic, WriterCallback};
+use crate::output::{self, Span};
+use crate::source_map::CodespanSourceMap;
+use crate::FileId;
+use codespan_reporting::diagnostic as csr_diag;
+use codespan_reporting::files::Files;
+use serde_json::{json, Map, Value};
+
+#[repr(C)]
+pub struct CodespanToolInfo {
+    pub name: *const u8,
+    pub name_length: libc::size_t,
+    pub version: *const u8,
+    pub version_length: libc::size_t,
+    pub information_uri: *const u8,
+    pub information_uri_length: libc::size_t,
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct Tool {
+    pub(crate) name: String,
+    pub(crate) version: Option<String>,
+    pub(crate) information_uri: Option<String>,
+}
+
+impl Tool {
+    pub(crate) unsafe fn from_raw(tool: *const CodespanToolInfo) -> Self {
+        if tool.is_null() {
+            return Self::default();
+        }
+
+        let tool = unsafe { &*tool };
+        let optional = |data: *const u8, length| {
+            (!data.is_null()).then(|| CodespanDiagnostic::utf8_to_string(data, length))
+        };
+        Self {
+            name: CodespanDiagnostic::utf8_to_string(tool.name, tool.name_length),
+            version: optional(tool.version, tool.version_length),
+            information_uri: optional(tool.information_uri, tool.information_uri_length),
+        }
+    }
+}
+
+impl CodespanDiagnostic {
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_write_sarif(
+        user_data: *mut libc::c_void,
+        diagnostics: *const *const Self,
+        diagnostics_length: libc::size_t,
+        source_map: *const CodespanSourceMap,
+        tool: *const CodespanToolInfo,
+        writer: Option<WriterCallback>,
+    ) {
+        if !source_map.is_null() {
+            let diagnostics =
+                unsafe { output::diagnostics_from_raw(diagnostics, diagnostics_length) };
+            let source_map = unsafe { &*source_map };
+            let tool = unsafe { Tool::from_raw(tool) };
+
+            let utf8_output =
+                serde_json::to_vec_pretty(&sarif_log(&diagnostics, source_map, &tool)).unwrap();
+            unsafe { (writer.unwrap())(user_data, utf8_output.as_ptr(), utf8_output.len()) };
+        }
+    }
+}
+
+pub(crate) fn sarif_log(
+    diagnostics: &[&CodespanDiagnostic],
+    source_map: &CodespanSourceMap,
+    tool: &Tool,
+) -> Value {
+    let mut rules: Vec<&str> = Vec::new();
+    let mut artifacts: Vec<FileId> = Vec::new();
+
+    let results: Vec<Value> = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let diagnostic = &diagnostic.diagnostic;
+            let mut result = Map::new();
+
+            if let Some(code) = &diagnostic.code {
+                let rule_index = rules
+                    .iter()
+                    .position(|rule| rule == code)
+                    .unwrap_or_else(|| {
+                        rules.push(code);
+                        rules.len() - 1
+                    });
+                result.insert("ruleId".to_owned(), json!(code));
+                result.insert("ruleIndex".to_owned(), json!(rule_index));
+            }
+            result.insert("level".to_owned(), json!(level(diagnostic.severity)));
+            result.insert(
+                "message".to_owned(),
+                json!({ "text": output::message_with_notes(diagnostic) }),
+            );
+
+            let mut location = |label: &csr_diag::Label<FileId>| {
+                let artifact_index = artifacts
+                    .iter()
+                    .position(|id| *id == label.file_id)
+                    .unwrap_or_else(|| {
+                        artifacts.push(label.file_id);
+                        artifacts.len() - 1
+                    });
+                physical_location(source_map, label, artifact_index)
+            };
+
+            let locations: Vec<Value> = diagnostic
+                .labels
+                .iter()
+                .filter(|label| label.style == csr_diag::LabelStyle::Primary)
+                .map(&mut location)
+                .collect();
+            let related_locations: Vec<Value> = output::secondary_labels(diagnostic)
+                .enumerate()
+                .map(|(id, label)| {
+                    let mut related = location(label);
+                    related["id"] = json!(id);
+                    related
+                })
+                .collect();
+
+            if !locations.is_empty() {
+                result.insert("locations".to_owned(), Value::Array(locations));
+            }
+            if !related_locations.is_empty() {
+                result.insert(
+                    "relatedLocations".to_owned(),
+                    Value::Array(related_locations),
+                );
+            }
+            Value::Object(result)
+        })
+        .collect();
+
+    let mut driver = Map::new();
+    driver.insert("name".to_owned(), json!(tool.name));
+    if let Some(version) = &tool.version {
+        driver.insert("version".to_owned(), json!(version));
+    }
+    if let Some(information_uri) = &tool.information_uri {
+        driver.insert("informationUri".to_owned(), json!(information_uri));
+    }
+    driver.insert(
+        "rules".to_owned(),
+        rules.iter().map(|rule| json!({ "id": rule })).collect(),
+    );
+
+    let artifacts: Vec<Value> = artifacts
+        .iter()
+        .map(|id| json!({ "location": { "uri": artifact_uri(source_map, *id) } }))
+        .collect();
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": { "driver": driver },
+            "columnKind": "unicodeCodePoints",
+            "artifacts": artifacts,
+            "results": results,
+        }],
+    })
+}
+
+fn level(severity: csr_diag::Severity) -> &'static str {
+    match severity {
+        csr_diag::Severity::Bug | csr_diag::Severity::Error => "error",
+        csr_diag::Severity::Warning => "warning",
+        csr_diag::Severity::Note | csr_diag::Severity::Help => "note",
+    }
+}
+
+fn artifact_uri(source_map: &CodespanSourceMap, file_id: FileId) -> String {
+    source_map
+        .name(file_id)
+        .map(output::file_uri)
+        .unwrap_or_default()
+}
+
+fn physical_location(
+    source_map: &CodespanSourceMap,
+    label: &csr_diag::Label<FileId>,
+    artifact_index: usize,
+) -> Value {
+    let mut region = json!({
+        "byteOffset": label.range.start,
+        "byteLength": label.range.len(),
+    });
+    if let Ok(Span { start, end }) = output::label_span(source_map, label) {
+        region["startLine"] = json!(start.line_number);
+        region["startColumn"] = json!(start.column_number);
+        region["endLine"] = json!(end.line_number);
+        region["endColumn"] = json!(end.column_number);
+    }
+
+    let mut location = json!({
+        "physicalLocation": {
+            "artifactLocation": {
+                "uri": artifact_uri(source_map, label.file_id),
+                "index": artifact_index,
+            },
+            "region": region,
+        },
+    });
+    if !label.message.is_empty() {
+        location["message"] = json!({ "text": label.message });
+    }
+    location
+}
@@ -0,0 +1,95 @@
+use crate::diagnostic::CodespanDiagnostic;
+use crate::source_map::{self, CodespanTextEdit, LineRange};
+use crate::status::{Status, STATUS_INVALID_ARGUMENT, STATUS_OK};
+use crate::FileId;
+
+pub type RebaseMode = libc::size_t;
+pub const REBASE_MODE_DROP: RebaseMode = 0;
+pub const REBASE_MODE_FLAG: RebaseMode = 1;
+
+const EDITED_NOTE: &str =
+    "the code this diagnostic refers to has been edited since it was produced";
+
+impl CodespanDiagnostic {
+    // Moves the labels in `file_id` to where their code ended up after the
+    // edits, which use the same rules as `codespan_source_map_apply_edits`.
+    // Stores the number of labels that overlapped an edit in `edited`, which
+    // may be null. Nothing is moved if any edit ends before it starts. The
+    // moved labels no longer match a snapshot, so it is dropped.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_diagnostic_rebase(
+        diagnostic: *mut Self,
+        file_id: FileId,
+        edits: *const CodespanTextEdit,
+        edit_count: libc::size_t,
+        mode: RebaseMode,
+        edited: *mut libc::size_t,
+    ) -> Status {
+        if diagnostic.is_null() {
+            return STATUS_INVALID_ARGUMENT;
+        }
+
+        let edits = source_map::text_edits(edits, edit_count);
+        if edits.iter().any(|(range, _)| range.start > range.end) {
+            return STATUS_INVALID_ARGUMENT;
+        }
+
+        let diagnostic = unsafe { &mut *diagnostic };
+        if !edits.is_empty()
+            && (diagnostic.diagnostic.labels)
+                .iter()
+                .any(|label| label.file_id == file_id)
+        {
+            diagnostic.snapshot = None;
+        }
+
+        let mut overlapped_count = 0;
+        let mut ranges = Vec::new();
+        diagnostic.diagnostic.labels.retain_mut(|label| {
+            let mut overlapped = false;
+            if label.file_id == file_id {
+                for (range, text) in &edits {
+                    let (range, untouched) = rebase(label.range.clone(), range.clone(), text.len());
+                    overlapped |= !untouched;
+                    label.range = range;
+                }
+            }
+            overlapped_count += overlapped as usize;
+            let keep = !overlapped || mode == REBASE_MODE_FLAG;
+            ranges.push(keep.then(|| label.range.clone()));
+            keep
+        });
+        if let Some(sources) = &mut diagnostic.sources {
+            sources.rebase(&ranges);
+        }
+
+        let diagnostic = &mut diagnostic.diagnostic;
+
+        if overlapped_count > 0
+            && mode == REBASE_MODE_FLAG
+            && !diagnostic.notes.iter().any(|note| note == EDITED_NOTE)
+        {
+            diagnostic.notes.push(EDITED_NOTE.to_owned());
+        }
+        if let Some(edited) = unsafe { edited.as_mut() } {
+            *edited = overlapped_count;
+        }
+        STATUS_OK
+    }
+}
+
+// Returns the label's new range, and whether it was untouched by the edit.
+// A label the edit overlaps grows to cover the replacement text.
+fn rebase(label: LineRange, edit: LineRange, text_length: usize) -> (LineRange, bool) {
+    let shift = |index: usize| index + text_length - (edit.end - edit.start);
+    if label.end <= edit.start {
+        (label, true)
+    } else if label.start >= edit.end {
+        (shift(label.start)..shift(label.end), true)
+    } else {
+        (
+            label.start.min(edit.start)..shift(label.end.max(edit.end)),
+            false,
+        )
+    }
+}
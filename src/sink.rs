@@ -0,0 +1,218 @@
+use crate::color::ColorMode;
+use crate::config::CodespanConfig;
+use crate::diagnostic::{
+    CharStyle, CodespanDiagnostic, DisplayStyle, FormatOptions, OutputFormat, WriterCallback,
+};
+use crate::output::sarif::{self, CodespanToolInfo, Tool};
+use crate::output::{checkstyle, gitlab, junit};
+use crate::source_map::CodespanSourceMap;
+use codespan_reporting as csr;
+
+pub struct CodespanSink {
+    user_data: *mut libc::c_void,
+    writer: WriterCallback,
+    kind: SinkKind,
+}
+
+enum SinkKind {
+    Stream {
+        format: OutputFormat,
+        format_options: FormatOptions,
+        config: Option<Box<csr::term::Config>>,
+        styles: Option<Box<csr::term::Styles>>,
+        color: ColorMode,
+    },
+    Sarif(Tool),
+    GitlabCodeQuality,
+    Checkstyle,
+    Junit(String),
+}
+
+// What a sink does with a single diagnostic: streaming sinks write it
+// straight away, the others collect it until the session is finished.
+pub(crate) enum SinkOutput {
+    Write(Vec<u8>),
+    Buffer(Box<CodespanDiagnostic>),
+}
+
+impl CodespanSink {
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_new_sink(
+        user_data: *mut libc::c_void,
+        format: OutputFormat,
+        options: FormatOptions,
+        color: ColorMode,
+        writer: Option<WriterCallback>,
+    ) -> *mut Self {
+        Self::new(
+            user_data,
+            writer,
+            SinkKind::Stream {
+                format: CodespanDiagnostic::output_format(format),
+                format_options: options,
+                config: None,
+                styles: None,
+                color,
+            },
+        )
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_new_sarif_sink(
+        user_data: *mut libc::c_void,
+        tool: *const CodespanToolInfo,
+        writer: Option<WriterCallback>,
+    ) -> *mut Self {
+        Self::new(
+            user_data,
+            writer,
+            SinkKind::Sarif(unsafe { Tool::from_raw(tool) }),
+        )
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_new_gitlab_code_quality_sink(
+        user_data: *mut libc::c_void,
+        writer: Option<WriterCallback>,
+    ) -> *mut Self {
+        Self::new(user_data, writer, SinkKind::GitlabCodeQuality)
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_new_checkstyle_sink(
+        user_data: *mut libc::c_void,
+        writer: Option<WriterCallback>,
+    ) -> *mut Self {
+        Self::new(user_data, writer, SinkKind::Checkstyle)
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_new_junit_sink(
+        user_data: *mut libc::c_void,
+        suite_name: *const u8,
+        suite_name_length: libc::size_t,
+        writer: Option<WriterCallback>,
+    ) -> *mut Self {
+        let suite_name = CodespanDiagnostic::utf8_to_string(suite_name, suite_name_length);
+        Self::new(user_data, writer, SinkKind::Junit(suite_name))
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_delete_sink(sink: *mut Self) {
+        if !sink.is_null() {
+            unsafe { drop(Box::from_raw(sink)) }
+        }
+    }
+
+    // Overrides the config of every diagnostic written to a streaming sink.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_sink_set_config(
+        sink: *mut Self,
+        style: DisplayStyle,
+        chars: CharStyle,
+        tab_width: libc::size_t,
+    ) {
+        if !sink.is_null()
+            && let SinkKind::Stream { config, .. } = &mut unsafe { &mut *sink }.kind
+        {
+            let config = config.get_or_insert_with(Default::default);
+            CodespanDiagnostic::apply_config(config, style, chars, tab_width);
+        }
+    }
+
+    // Overrides the config and styles of every diagnostic written to a
+    // streaming sink, and its color mode.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_sink_apply_config(
+        sink: *mut Self,
+        config: *const CodespanConfig,
+    ) {
+        if !sink.is_null()
+            && !config.is_null()
+            && let SinkKind::Stream {
+                config: sink_config,
+                styles,
+                color,
+                ..
+            } = &mut unsafe { &mut *sink }.kind
+        {
+            let config = unsafe { &*config };
+            *sink_config = Some(Box::new(config.config.clone()));
+            *styles = Some(Box::new(config.styles.clone()));
+            *color = config.color;
+        }
+    }
+
+    fn new(
+        user_data: *mut libc::c_void,
+        writer: Option<WriterCallback>,
+        kind: SinkKind,
+    ) -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            user_data,
+            writer: writer.unwrap(),
+            kind,
+        }))
+    }
+
+    pub(crate) fn render(
+        &self,
+        diagnostic: &CodespanDiagnostic,
+        source_map: &CodespanSourceMap,
+    ) -> Result<SinkOutput, csr::files::Error> {
+        match &self.kind {
+            SinkKind::Stream {
+                format,
+                format_options,
+                config,
+                styles,
+                color,
+            } => {
+                let mut diagnostic = diagnostic.clone();
+                diagnostic.format = *format;
+                diagnostic.format_options = *format_options;
+                if let Some(config) = config {
+                    diagnostic.config = (**config).clone();
+                }
+                if let Some(styles) = styles {
+                    diagnostic.styles = (**styles).clone();
+                }
+                Ok(SinkOutput::Write(
+                    diagnostic.render_to_vec(source_map, *color, None)?,
+                ))
+            }
+            _ => Ok(SinkOutput::Buffer(Box::new(diagnostic.clone()))),
+        }
+    }
+
+    pub(crate) unsafe fn write(&self, utf8_output: &[u8]) {
+        unsafe { (self.writer)(self.user_data, utf8_output.as_ptr(), utf8_output.len()) };
+    }
+
+    // Writes the collected diagnostics of a structured sink as one report.
+    pub(crate) unsafe fn finish(
+        &self,
+        diagnostics: &[CodespanDiagnostic],
+        source_map: &CodespanSourceMap,
+    ) {
+        let diagnostics: Vec<&CodespanDiagnostic> = diagnostics.iter().collect();
+        let utf8_output = match &self.kind {
+            SinkKind::Stream { .. } => return,
+            SinkKind::Sarif(tool) => {
+                serde_json::to_vec_pretty(&sarif::sarif_log(&diagnostics, source_map, tool))
+                    .unwrap()
+            }
+            SinkKind::GitlabCodeQuality => {
+                serde_json::to_vec_pretty(&gitlab::code_quality_report(&diagnostics, source_map))
+                    .unwrap()
+            }
+            SinkKind::Checkstyle => {
+                checkstyle::checkstyle_report(&diagnostics, source_map).into_bytes()
+            }
+            SinkKind::Junit(suite_name) => {
+                junit::junit_report(&diagnostics, source_map, suite_name).into_bytes()
+            }
+        };
+        unsafe { self.write(&utf8_output) };
+    }
+}
@@ -0,0 +1,38 @@
+use crate::diagnostic::CodespanDiagnostic;
+use crate::source_map::CodespanSourceMap;
+use crate::status::{self, Status, STATUS_INVALID_ARGUMENT, STATUS_OK};
+use std::sync::Arc;
+
+impl CodespanDiagnostic {
+    // Copies every file the diagnostic's labels refer to out of `source_map`,
+    // after which the diagnostic can be written with a null source map, on
+    // any thread, and after the host has freed its buffers.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_diagnostic_snapshot(
+        diagnostic: *mut Self,
+        source_map: *const CodespanSourceMap,
+    ) -> Status {
+        if diagnostic.is_null() {
+            return STATUS_INVALID_ARGUMENT;
+        }
+
+        let diagnostic = unsafe { &mut *diagnostic };
+        // Taking a new snapshot reads the files from `source_map` again.
+        let Some(source_map) = (unsafe { source_map.as_ref() }).or(diagnostic.snapshot.as_deref())
+        else {
+            return STATUS_INVALID_ARGUMENT;
+        };
+        let file_ids = diagnostic
+            .diagnostic
+            .labels
+            .iter()
+            .map(|label| label.file_id);
+        match source_map.snapshot(file_ids) {
+            Ok(snapshot) => {
+                diagnostic.snapshot = Some(Arc::new(snapshot));
+                STATUS_OK
+            }
+            Err(error) => status::from_error(&error),
+        }
+    }
+}
@@ -1,7 +1,19 @@
+use crate::diagnostic::CodespanDiagnostic;
 use crate::{ByteIndex, FileId};
 use codespan_reporting as csr;
 use libc;
-use std::{ops, slice};
+use std::{io, ops, ptr, slice};
+
+mod filesystem;
+mod overlay;
+mod owned;
+mod snapshot;
+
+use filesystem::Filesystem;
+use overlay::Overlay;
+use owned::Owned;
+pub use owned::{CodespanTextEdit, Version};
+use snapshot::Snapshot;
 
 pub type LineIndex = libc::size_t;
 pub type LineRange = ops::Range<libc::size_t>;
@@ -40,8 +52,21 @@ pub type ColumnNumberCallback = unsafe extern "C" fn(
     byte_index: ByteIndex,
 ) -> libc::size_t;
 
-#[repr(C)]
+pub const FILE_ID_INVALID: FileId = FileId::MAX;
+
 pub struct CodespanSourceMap {
+    backend: Backend,
+}
+
+enum Backend {
+    Callbacks(Callbacks),
+    Filesystem(Filesystem),
+    Overlay(Overlay),
+    Owned(Owned),
+    Snapshot(Snapshot),
+}
+
+struct Callbacks {
     user_data: *mut libc::c_void,
     file_name: FileNameCallback,
     source_code: SourceCodeCallBack,
@@ -51,6 +76,13 @@ pub struct CodespanSourceMap {
     column_number: Option<ColumnNumberCallback>,
 }
 
+// The source map only hands `user_data` back to its callbacks, so sharing it
+// between threads is sound as long as those callbacks may be called
+// concurrently. The filesystem backend does its own locking, and overlays
+// are only changed through a `*mut` the caller must not share.
+unsafe impl Send for CodespanSourceMap {}
+unsafe impl Sync for CodespanSourceMap {}
+
 impl CodespanSourceMap {
     #[unsafe(no_mangle)]
     pub unsafe extern "C" fn codespan_new_source_map(
@@ -63,16 +95,167 @@ impl CodespanSourceMap {
         column_number: Option<ColumnNumberCallback>,
     ) -> *mut Self {
         Box::into_raw(Box::new(Self {
-            user_data,
-            file_name: file_name.unwrap(),
-            source_code: source_code.unwrap(),
-            line_index: line_index.unwrap(),
-            line_range: line_range.unwrap(),
-            line_number,
-            column_number,
+            backend: Backend::Callbacks(Callbacks {
+                user_data,
+                file_name: file_name.unwrap(),
+                source_code: source_code.unwrap(),
+                line_index: line_index.unwrap(),
+                line_range: line_range.unwrap(),
+                line_number,
+                column_number,
+            }),
+        }))
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_new_filesystem_source_map() -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            backend: Backend::Filesystem(Filesystem::default()),
+        }))
+    }
+
+    // Returns the id of the file at `path`, registering it if needed. The
+    // file is not read until a diagnostic needs it.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_source_map_add_path(
+        source_map: *const Self,
+        path: *const u8,
+        path_length: libc::size_t,
+    ) -> FileId {
+        match unsafe { source_map.as_ref() }.map(|source_map| &source_map.backend) {
+            Some(Backend::Filesystem(filesystem)) => {
+                filesystem.add_path(&CodespanDiagnostic::utf8_to_string(path, path_length))
+            }
+            _ => FILE_ID_INVALID,
+        }
+    }
+
+    // Files of at least `threshold` bytes are memory mapped instead of read,
+    // 0 disables memory mapping. Mapped files must not be modified while the
+    // source map exists.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_source_map_set_mmap_threshold(
+        source_map: *mut Self,
+        threshold: libc::size_t,
+    ) {
+        if let Some(Backend::Filesystem(filesystem)) =
+            unsafe { source_map.as_mut() }.map(|source_map| &mut source_map.backend)
+        {
+            filesystem.mmap_threshold = threshold;
+        }
+    }
+
+    // The overlay looks files up in `base`, which must outlive it.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_new_overlay_source_map(base: *const Self) -> *mut Self {
+        if base.is_null() {
+            return ptr::null_mut();
+        }
+
+        Box::into_raw(Box::new(Self {
+            backend: Backend::Overlay(Overlay::new(base)),
+        }))
+    }
+
+    // Replaces the contents of the file named `path` in the base map. Must
+    // not be called while a diagnostic is being rendered with this map.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_source_map_set_overlay(
+        source_map: *mut Self,
+        path: *const u8,
+        path_length: libc::size_t,
+        source: *const u8,
+        source_length: libc::size_t,
+    ) -> bool {
+        match unsafe { source_map.as_mut() }.map(|source_map| &mut source_map.backend) {
+            Some(Backend::Overlay(overlay)) => {
+                overlay.set(
+                    CodespanDiagnostic::utf8_to_string(path, path_length),
+                    CodespanDiagnostic::utf8_to_string(source, source_length),
+                );
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Returns whether `path` had an overlay.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_source_map_clear_overlay(
+        source_map: *mut Self,
+        path: *const u8,
+        path_length: libc::size_t,
+    ) -> bool {
+        match unsafe { source_map.as_mut() }.map(|source_map| &mut source_map.backend) {
+            Some(Backend::Overlay(overlay)) => {
+                overlay.clear(&CodespanDiagnostic::utf8_to_string(path, path_length))
+            }
+            _ => false,
+        }
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_new_owned_source_map() -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            backend: Backend::Owned(Owned::default()),
         }))
     }
 
+    // Copies `source` into the map, which owns it from then on.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_source_map_add_file(
+        source_map: *mut Self,
+        name: *const u8,
+        name_length: libc::size_t,
+        source: *const u8,
+        source_length: libc::size_t,
+    ) -> FileId {
+        match unsafe { source_map.as_mut() }.map(|source_map| &mut source_map.backend) {
+            Some(Backend::Owned(owned)) => owned.add_file(
+                CodespanDiagnostic::utf8_to_string(name, name_length),
+                CodespanDiagnostic::utf8_to_string(source, source_length),
+            ),
+            _ => FILE_ID_INVALID,
+        }
+    }
+
+    // Applies the edits in order. Returns false and leaves the file unchanged
+    // if any range is out of bounds or splits a UTF-8 character. Must not be
+    // called while a diagnostic is being rendered with this map.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_source_map_apply_edits(
+        source_map: *mut Self,
+        file_id: FileId,
+        edits: *const CodespanTextEdit,
+        edit_count: libc::size_t,
+    ) -> bool {
+        let Some(Backend::Owned(owned)) =
+            unsafe { source_map.as_mut() }.map(|source_map| &mut source_map.backend)
+        else {
+            return false;
+        };
+
+        let edits = text_edits(edits, edit_count);
+        let edits: Vec<(LineRange, &str)> = edits
+            .iter()
+            .map(|(range, text)| (range.clone(), text.as_str()))
+            .collect();
+        owned.apply_edits(file_id, &edits)
+    }
+
+    // Versions start at 1 and are bumped by every successful
+    // `codespan_source_map_apply_edits`. Returns 0 for unknown files.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_source_map_file_version(
+        source_map: *const Self,
+        file_id: FileId,
+    ) -> Version {
+        match unsafe { source_map.as_ref() }.map(|source_map| &source_map.backend) {
+            Some(Backend::Owned(owned)) => owned.version(file_id).unwrap_or(0),
+            _ => 0,
+        }
+    }
+
     #[unsafe(no_mangle)]
     pub unsafe extern "C" fn codespan_delete_source_map(source_map: *mut Self) {
         if !source_map.is_null() {
@@ -83,39 +266,95 @@ impl CodespanSourceMap {
     }
 }
 
+impl CodespanSourceMap {
+    // Copies the given files out of `self`, so they can be rendered after
+    // `self` or the host's buffers are gone.
+    pub(crate) fn snapshot(
+        &self,
+        file_ids: impl IntoIterator<Item = FileId>,
+    ) -> Result<Self, csr::files::Error> {
+        Ok(Self {
+            backend: Backend::Snapshot(Snapshot::capture(self, file_ids)?),
+        })
+    }
+}
+
+macro_rules! dispatch {
+    ($self:ident.$method:ident($($arg:expr),*)) => {
+        match &$self.backend {
+            Backend::Callbacks(callbacks) => callbacks.$method($($arg),*),
+            Backend::Filesystem(filesystem) => filesystem.$method($($arg),*),
+            Backend::Overlay(overlay) => overlay.$method($($arg),*),
+            Backend::Owned(owned) => owned.$method($($arg),*),
+            Backend::Snapshot(snapshot) => snapshot.$method($($arg),*),
+        }
+    };
+}
+
 impl<'a> csr::files::Files<'a> for CodespanSourceMap {
     type FileId = FileId;
     type Name = &'a str;
     type Source = &'a str;
 
     fn name(&'a self, id: Self::FileId) -> Result<Self::Name, csr::files::Error> {
-        Ok(str::from_utf8({
-            let mut file_length = 0;
-            let file_name_buffer =
-                unsafe { (self.file_name)(self.user_data, id, &mut file_length) };
+        dispatch!(self.name(id))
+    }
 
-            if file_name_buffer.is_null() {
-                panic!("file name buffer is NULL in CodespanSourceMap file_name");
-            }
+    fn source(&'a self, id: Self::FileId) -> Result<Self::Source, csr::files::Error> {
+        dispatch!(self.source(id))
+    }
 
-            unsafe { slice::from_raw_parts(file_name_buffer, file_length) }
-        })
-        .unwrap())
+    fn line_index(
+        &'a self,
+        id: Self::FileId,
+        byte_index: ByteIndex,
+    ) -> Result<usize, csr::files::Error> {
+        dispatch!(self.line_index(id, byte_index))
     }
 
-    fn source(&'a self, id: Self::FileId) -> Result<Self::Source, csr::files::Error> {
-        Ok(str::from_utf8({
-            let mut source_code_length = 0;
-            let source_code_buffer =
-                unsafe { (self.source_code)(self.user_data, id, &mut source_code_length) };
+    fn line_range(
+        &'a self,
+        id: Self::FileId,
+        line_index: LineIndex,
+    ) -> Result<LineRange, csr::files::Error> {
+        dispatch!(self.line_range(id, line_index))
+    }
 
-            if source_code_buffer.is_null() {
-                panic!("source code buffer is NULL in CodespanSourceMap file_name");
-            }
+    fn line_number(
+        &'a self,
+        id: Self::FileId,
+        line_index: LineIndex,
+    ) -> Result<usize, codespan_reporting::files::Error> {
+        dispatch!(self.line_number(id, line_index))
+    }
 
-            unsafe { slice::from_raw_parts(source_code_buffer, source_code_length) }
-        })
-        .unwrap())
+    fn column_number(
+        &'a self,
+        id: Self::FileId,
+        line_index: LineIndex,
+        byte_index: ByteIndex,
+    ) -> Result<usize, codespan_reporting::files::Error> {
+        dispatch!(self.column_number(id, line_index, byte_index))
+    }
+}
+
+impl<'a> csr::files::Files<'a> for Callbacks {
+    type FileId = FileId;
+    type Name = &'a str;
+    type Source = &'a str;
+
+    // A null buffer means the host cannot provide the file.
+    fn name(&'a self, id: Self::FileId) -> Result<Self::Name, csr::files::Error> {
+        let mut file_length = 0;
+        let file_name_buffer = unsafe { (self.file_name)(self.user_data, id, &mut file_length) };
+        utf8_buffer(file_name_buffer, file_length)
+    }
+
+    fn source(&'a self, id: Self::FileId) -> Result<Self::Source, csr::files::Error> {
+        let mut source_code_length = 0;
+        let source_code_buffer =
+            unsafe { (self.source_code)(self.user_data, id, &mut source_code_length) };
+        utf8_buffer(source_code_buffer, source_code_length)
     }
 
     fn line_index(
@@ -159,9 +398,61 @@ impl<'a> csr::files::Files<'a> for CodespanSourceMap {
         } else {
             let source = self.source(id)?;
             let line_range = self.line_range(id, line_index)?;
-            let column_index = csr::files::column_index(source.as_ref(), line_range, byte_index);
+            let column_index = csr::files::column_index(source, line_range, byte_index);
 
             Ok(column_index + 1)
         }
     }
 }
+
+fn utf8_buffer<'a>(buffer: *const u8, length: libc::size_t) -> Result<&'a str, csr::files::Error> {
+    if buffer.is_null() {
+        return Err(csr::files::Error::FileMissing);
+    }
+
+    str::from_utf8(unsafe { slice::from_raw_parts(buffer, length) })
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error).into())
+}
+
+pub(crate) fn text_edits(
+    edits: *const CodespanTextEdit,
+    edit_count: libc::size_t,
+) -> Vec<(LineRange, String)> {
+    if edits.is_null() {
+        return Vec::new();
+    }
+
+    unsafe { slice::from_raw_parts(edits, edit_count) }
+        .iter()
+        .map(|edit| {
+            (
+                edit.start..edit.end,
+                CodespanDiagnostic::utf8_to_string(edit.text, edit.text_length),
+            )
+        })
+        .collect()
+}
+
+// Shared by the backends that keep their own line starts, as computed by
+// `csr::files::line_starts`.
+pub(crate) fn line_index(line_starts: &[usize], byte_index: ByteIndex) -> LineIndex {
+    line_starts
+        .binary_search(&byte_index)
+        .unwrap_or_else(|next_line| next_line - 1)
+}
+
+pub(crate) fn line_range(
+    line_starts: &[usize],
+    source_length: usize,
+    line_index: LineIndex,
+) -> Result<LineRange, csr::files::Error> {
+    let line_start = |line_index: LineIndex| match line_index.cmp(&line_starts.len()) {
+        std::cmp::Ordering::Less => Ok(line_starts[line_index]),
+        std::cmp::Ordering::Equal => Ok(source_length),
+        std::cmp::Ordering::Greater => Err(csr::files::Error::LineTooLarge {
+            given: line_index,
+            max: line_starts.len() - 1,
+        }),
+    };
+    Ok(line_start(line_index)?..line_start(line_index + 1)?)
+}
@@ -0,0 +1,184 @@
+use super::{LineIndex, LineRange};
+use crate::{ByteIndex, FileId};
+use codespan_reporting as csr;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::fd::AsRawFd;
+use std::sync::{Mutex, OnceLock};
+use std::{ptr, slice, str};
+
+#[derive(Default)]
+pub(crate) struct Filesystem {
+    files: Mutex<Files>,
+    pub(crate) mmap_threshold: usize,
+}
+
+#[derive(Default)]
+struct Files {
+    // Files are boxed and never removed, so references to them stay valid
+    // for as long as the source map lives.
+    #[allow(clippy::vec_box)]
+    files: Vec<Box<LazyFile>>,
+    ids: HashMap<String, FileId>,
+}
+
+struct LazyFile {
+    path: String,
+    contents: OnceLock<Contents>,
+}
+
+struct Contents {
+    source: Source,
+    line_starts: Vec<usize>,
+}
+
+enum Source {
+    Read(String),
+    Mapped(Mapping),
+}
+
+struct Mapping {
+    data: *mut libc::c_void,
+    length: usize,
+}
+
+// The mapping is only ever read. See `Source::as_str` for why that does not
+// make it immutable.
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.data, self.length) };
+    }
+}
+
+impl Source {
+    fn as_str(&self) -> &str {
+        match self {
+            Source::Read(source) => source,
+            // SAFETY: Checked to be UTF-8 when the file was mapped. A private
+            // mapping still shows writes made to the file by other processes,
+            // so this only holds while nobody modifies the file. Doing so is
+            // undefined behaviour, which callers accept when they enable
+            // `codespan_source_map_set_mmap_threshold`.
+            Source::Mapped(mapping) => unsafe {
+                str::from_utf8_unchecked(slice::from_raw_parts(
+                    mapping.data as *const u8,
+                    mapping.length,
+                ))
+            },
+        }
+    }
+}
+
+impl Filesystem {
+    pub(crate) fn add_path(&self, path: &str) -> FileId {
+        let mut files = self.files.lock().unwrap();
+        if let Some(id) = files.ids.get(path) {
+            return *id;
+        }
+
+        let id = files.files.len();
+        files.files.push(Box::new(LazyFile {
+            path: path.to_owned(),
+            contents: OnceLock::new(),
+        }));
+        files.ids.insert(path.to_owned(), id);
+        id
+    }
+
+    fn file(&self, id: FileId) -> Result<&LazyFile, csr::files::Error> {
+        let files = self.files.lock().unwrap();
+        let file = files.files.get(id).ok_or(csr::files::Error::FileMissing)?;
+        // SAFETY: Each file is boxed, so it does not move when `files` grows,
+        // and files are never removed or replaced. The reference therefore
+        // stays valid after the lock is released, for as long as `self`.
+        Ok(unsafe { &*(&**file as *const LazyFile) })
+    }
+
+    // Failed reads are not cached, so a file that appears later can still
+    // be loaded.
+    fn contents(&self, id: FileId) -> Result<&Contents, csr::files::Error> {
+        let file = self.file(id)?;
+        if let Some(contents) = file.contents.get() {
+            return Ok(contents);
+        }
+
+        let source = self.load(&file.path)?;
+        let line_starts = csr::files::line_starts(source.as_str()).collect();
+        Ok(file.contents.get_or_init(|| Contents {
+            source,
+            line_starts,
+        }))
+    }
+
+    fn load(&self, path: &str) -> io::Result<Source> {
+        let mut file = File::open(path)?;
+        let length = file.metadata()?.len() as usize;
+
+        if self.mmap_threshold > 0 && length >= self.mmap_threshold {
+            let data = unsafe {
+                libc::mmap(
+                    ptr::null_mut(),
+                    length,
+                    libc::PROT_READ,
+                    libc::MAP_PRIVATE,
+                    file.as_raw_fd(),
+                    0,
+                )
+            };
+            if data == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            let mapping = Mapping { data, length };
+            let bytes = unsafe { slice::from_raw_parts(data as *const u8, length) };
+            str::from_utf8(bytes)
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+            return Ok(Source::Mapped(mapping));
+        }
+
+        let mut source = String::with_capacity(length);
+        file.read_to_string(&mut source)?;
+        Ok(Source::Read(source))
+    }
+}
+
+impl<'a> csr::files::Files<'a> for Filesystem {
+    type FileId = FileId;
+    type Name = &'a str;
+    type Source = &'a str;
+
+    fn name(&'a self, id: FileId) -> Result<&'a str, csr::files::Error> {
+        Ok(&self.file(id)?.path)
+    }
+
+    fn source(&'a self, id: FileId) -> Result<&'a str, csr::files::Error> {
+        Ok(self.contents(id)?.source.as_str())
+    }
+
+    fn line_index(
+        &'a self,
+        id: FileId,
+        byte_index: ByteIndex,
+    ) -> Result<LineIndex, csr::files::Error> {
+        Ok(super::line_index(
+            &self.contents(id)?.line_starts,
+            byte_index,
+        ))
+    }
+
+    fn line_range(
+        &'a self,
+        id: FileId,
+        line_index: LineIndex,
+    ) -> Result<LineRange, csr::files::Error> {
+        let contents = self.contents(id)?;
+        super::line_range(
+            &contents.line_starts,
+            contents.source.as_str().len(),
+            line_index,
+        )
+    }
+}
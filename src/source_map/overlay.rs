@@ -0,0 +1,113 @@
+use super::{CodespanSourceMap, LineIndex, LineRange};
+use crate::{ByteIndex, FileId};
+use codespan_reporting as csr;
+use codespan_reporting::files::Files;
+use std::collections::HashMap;
+
+pub(crate) struct Overlay {
+    base: *const CodespanSourceMap,
+    files: HashMap<String, OverlayFile>,
+}
+
+struct OverlayFile {
+    source: String,
+    line_starts: Vec<usize>,
+}
+
+impl Overlay {
+    pub(crate) fn new(base: *const CodespanSourceMap) -> Self {
+        Self {
+            base,
+            files: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn set(&mut self, path: String, source: String) {
+        let line_starts = csr::files::line_starts(&source).collect();
+        self.files.insert(
+            path,
+            OverlayFile {
+                source,
+                line_starts,
+            },
+        );
+    }
+
+    pub(crate) fn clear(&mut self, path: &str) -> bool {
+        self.files.remove(path).is_some()
+    }
+
+    fn base(&self) -> &CodespanSourceMap {
+        unsafe { &*self.base }
+    }
+
+    // Overlays are keyed by the name the base map gives the file.
+    fn file(&self, id: FileId) -> Result<Option<&OverlayFile>, csr::files::Error> {
+        Ok(self.files.get(self.base().name(id)?))
+    }
+}
+
+impl<'a> csr::files::Files<'a> for Overlay {
+    type FileId = FileId;
+    type Name = &'a str;
+    type Source = &'a str;
+
+    fn name(&'a self, id: FileId) -> Result<&'a str, csr::files::Error> {
+        self.base().name(id)
+    }
+
+    fn source(&'a self, id: FileId) -> Result<&'a str, csr::files::Error> {
+        match self.file(id)? {
+            Some(file) => Ok(&file.source),
+            None => self.base().source(id),
+        }
+    }
+
+    fn line_index(
+        &'a self,
+        id: FileId,
+        byte_index: ByteIndex,
+    ) -> Result<LineIndex, csr::files::Error> {
+        match self.file(id)? {
+            Some(file) => Ok(super::line_index(&file.line_starts, byte_index)),
+            None => self.base().line_index(id, byte_index),
+        }
+    }
+
+    fn line_range(
+        &'a self,
+        id: FileId,
+        line_index: LineIndex,
+    ) -> Result<LineRange, csr::files::Error> {
+        match self.file(id)? {
+            Some(file) => super::line_range(&file.line_starts, file.source.len(), line_index),
+            None => self.base().line_range(id, line_index),
+        }
+    }
+
+    fn line_number(
+        &'a self,
+        id: FileId,
+        line_index: LineIndex,
+    ) -> Result<usize, csr::files::Error> {
+        match self.file(id)? {
+            Some(_) => Ok(line_index + 1),
+            None => self.base().line_number(id, line_index),
+        }
+    }
+
+    fn column_number(
+        &'a self,
+        id: FileId,
+        line_index: LineIndex,
+        byte_index: ByteIndex,
+    ) -> Result<usize, csr::files::Error> {
+        match self.file(id)? {
+            Some(file) => {
+                let line_range = self.line_range(id, line_index)?;
+                Ok(csr::files::column_index(&file.source, line_range, byte_index) + 1)
+            }
+            None => self.base().column_number(id, line_index, byte_index),
+        }
+    }
+}
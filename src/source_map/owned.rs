@@ -0,0 +1,130 @@
+use super::{LineIndex, LineRange};
+use crate::{ByteIndex, FileId};
+use codespan_reporting as csr;
+
+pub type Version = u64;
+
+// Replaces `start..end` with `text`. Ranges are byte offsets into the file
+// as left by the previous edit.
+#[repr(C)]
+pub struct CodespanTextEdit {
+    pub start: ByteIndex,
+    pub end: ByteIndex,
+    pub text: *const u8,
+    pub text_length: libc::size_t,
+}
+
+#[derive(Default)]
+pub(crate) struct Owned {
+    files: Vec<OwnedFile>,
+}
+
+#[derive(Clone)]
+struct OwnedFile {
+    name: String,
+    source: String,
+    line_starts: Vec<usize>,
+    version: Version,
+}
+
+impl Owned {
+    pub(crate) fn add_file(&mut self, name: String, source: String) -> FileId {
+        let line_starts = csr::files::line_starts(&source).collect();
+        self.files.push(OwnedFile {
+            name,
+            source,
+            line_starts,
+            version: 1,
+        });
+        self.files.len() - 1
+    }
+
+    // Either every edit is applied and the version is bumped, or the file is
+    // left unchanged.
+    pub(crate) fn apply_edits(&mut self, id: FileId, edits: &[(LineRange, &str)]) -> bool {
+        let Some(file) = self.files.get_mut(id) else {
+            return false;
+        };
+
+        let mut edited = file.clone();
+        for (range, text) in edits {
+            if !edited.apply_edit(range.clone(), text) {
+                return false;
+            }
+        }
+        edited.version += 1;
+        *file = edited;
+        true
+    }
+
+    pub(crate) fn version(&self, id: FileId) -> Option<Version> {
+        self.files.get(id).map(|file| file.version)
+    }
+
+    fn file(&self, id: FileId) -> Result<&OwnedFile, csr::files::Error> {
+        self.files.get(id).ok_or(csr::files::Error::FileMissing)
+    }
+}
+
+impl OwnedFile {
+    fn apply_edit(&mut self, range: LineRange, text: &str) -> bool {
+        if range.start > range.end
+            || !self.source.is_char_boundary(range.start)
+            || !self.source.is_char_boundary(range.end)
+        {
+            return false;
+        }
+
+        // Only the line starts inside the edited range are recomputed, the
+        // ones after it are shifted.
+        let first = self
+            .line_starts
+            .partition_point(|&start| start <= range.start);
+        let last = self
+            .line_starts
+            .partition_point(|&start| start <= range.end);
+        let inserted = text
+            .match_indices('\n')
+            .map(|(index, _)| range.start + index + 1);
+        self.line_starts.splice(first..last, inserted);
+
+        let shifted = first + text.matches('\n').count();
+        for start in &mut self.line_starts[shifted..] {
+            *start = *start + text.len() - (range.end - range.start);
+        }
+
+        self.source.replace_range(range, text);
+        true
+    }
+}
+
+impl<'a> csr::files::Files<'a> for Owned {
+    type FileId = FileId;
+    type Name = &'a str;
+    type Source = &'a str;
+
+    fn name(&'a self, id: FileId) -> Result<&'a str, csr::files::Error> {
+        Ok(&self.file(id)?.name)
+    }
+
+    fn source(&'a self, id: FileId) -> Result<&'a str, csr::files::Error> {
+        Ok(&self.file(id)?.source)
+    }
+
+    fn line_index(
+        &'a self,
+        id: FileId,
+        byte_index: ByteIndex,
+    ) -> Result<LineIndex, csr::files::Error> {
+        Ok(super::line_index(&self.file(id)?.line_starts, byte_index))
+    }
+
+    fn line_range(
+        &'a self,
+        id: FileId,
+        line_index: LineIndex,
+    ) -> Result<LineRange, csr::files::Error> {
+        let file = self.file(id)?;
+        super::line_range(&file.line_starts, file.source.len(), line_index)
+    }
+}
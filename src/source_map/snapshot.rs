@@ -0,0 +1,75 @@
+use super::{CodespanSourceMap, LineIndex, LineRange};
+use crate::{ByteIndex, FileId};
+use codespan_reporting as csr;
+use codespan_reporting::files::Files;
+use std::collections::HashMap;
+
+// Copies of files taken from another source map, under their original ids.
+pub(crate) struct Snapshot {
+    files: HashMap<FileId, SnapshotFile>,
+}
+
+struct SnapshotFile {
+    name: String,
+    source: String,
+    line_starts: Vec<usize>,
+}
+
+impl Snapshot {
+    pub(crate) fn capture(
+        source_map: &CodespanSourceMap,
+        file_ids: impl IntoIterator<Item = FileId>,
+    ) -> Result<Self, csr::files::Error> {
+        let mut files = HashMap::new();
+        for id in file_ids {
+            if files.contains_key(&id) {
+                continue;
+            }
+            let source = source_map.source(id)?.to_owned();
+            files.insert(
+                id,
+                SnapshotFile {
+                    name: source_map.name(id)?.to_owned(),
+                    line_starts: csr::files::line_starts(&source).collect(),
+                    source,
+                },
+            );
+        }
+        Ok(Self { files })
+    }
+
+    fn file(&self, id: FileId) -> Result<&SnapshotFile, csr::files::Error> {
+        self.files.get(&id).ok_or(csr::files::Error::FileMissing)
+    }
+}
+
+impl<'a> csr::files::Files<'a> for Snapshot {
+    type FileId = FileId;
+    type Name = &'a str;
+    type Source = &'a str;
+
+    fn name(&'a self, id: FileId) -> Result<&'a str, csr::files::Error> {
+        Ok(&self.file(id)?.name)
+    }
+
+    fn source(&'a self, id: FileId) -> Result<&'a str, csr::files::Error> {
+        Ok(&self.file(id)?.source)
+    }
+
+    fn line_index(
+        &'a self,
+        id: FileId,
+        byte_index: ByteIndex,
+    ) -> Result<LineIndex, csr::files::Error> {
+        Ok(super::line_index(&self.file(id)?.line_starts, byte_index))
+    }
+
+    fn line_range(
+        &'a self,
+        id: FileId,
+        line_index: LineIndex,
+    ) -> Result<LineRange, csr::files::Error> {
+        let file = self.file(id)?;
+        super::line_range(&file.line_starts, file.source.len(), line_index)
+    }
+}
@@ -0,0 +1,161 @@
+use crate::diagnostic::CodespanDiagnostic;
+use crate::fingerprint::{self, Fnv1a};
+use crate::output::plain::{self, Locus};
+use crate::source_map::{CodespanSourceMap, LineRange};
+use crate::status::{self, Status, STATUS_INVALID_ARGUMENT, STATUS_OK};
+use crate::FileId;
+use codespan_reporting as csr;
+use codespan_reporting::diagnostic as csr_diag;
+use codespan_reporting::files::Files;
+use codespan_reporting::term::termcolor::WriteColor;
+
+pub type StaleMode = libc::size_t;
+pub const STALE_MODE_NOTE: StaleMode = 0;
+pub const STALE_MODE_NO_SNIPPET: StaleMode = 1;
+
+const STALE_NOTE: &str = "source changed since this diagnostic was produced";
+
+#[derive(Clone)]
+pub(crate) struct SourceRecord {
+    mode: StaleMode,
+    // In the same order as the diagnostic's labels.
+    labels: Vec<LabelSource>,
+}
+
+// What a label pointed at when the record was taken.
+#[derive(Clone)]
+struct LabelSource {
+    file_id: FileId,
+    range: LineRange,
+    hash: u64,
+    name: String,
+    location: csr::files::Location,
+}
+
+impl CodespanDiagnostic {
+    // Records a hash of the lines each label points at, so that rendering
+    // can tell when `source_map` later returns different text for them.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_diagnostic_record_sources(
+        diagnostic: *mut Self,
+        source_map: *const CodespanSourceMap,
+        mode: StaleMode,
+    ) -> Status {
+        if diagnostic.is_null() {
+            return STATUS_INVALID_ARGUMENT;
+        }
+
+        let diagnostic = unsafe { &mut *diagnostic };
+        let Some(source_map) = (unsafe { diagnostic.source_map(source_map) }) else {
+            return STATUS_INVALID_ARGUMENT;
+        };
+        let labels: Result<Vec<LabelSource>, _> = diagnostic
+            .diagnostic
+            .labels
+            .iter()
+            .map(|label| {
+                Ok(LabelSource {
+                    file_id: label.file_id,
+                    range: label.range.clone(),
+                    hash: hash(source_map, label)?,
+                    name: source_map.name(label.file_id)?.to_owned(),
+                    location: source_map.location(label.file_id, label.range.start)?,
+                })
+            })
+            .collect();
+        match labels {
+            Ok(labels) => {
+                diagnostic.sources = Some(SourceRecord {
+                    mode: match mode {
+                        STALE_MODE_NO_SNIPPET => STALE_MODE_NO_SNIPPET,
+                        _ => STALE_MODE_NOTE,
+                    },
+                    labels,
+                });
+                STATUS_OK
+            }
+            Err(error) => status::from_error(&error),
+        }
+    }
+
+    // Returns false if no sources were recorded.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_diagnostic_is_stale(
+        diagnostic: *const Self,
+        source_map: *const CodespanSourceMap,
+    ) -> bool {
+        let Some(diagnostic) = (unsafe { diagnostic.as_ref() }) else {
+            return false;
+        };
+        let Some(source_map) = (unsafe { diagnostic.source_map(source_map) }) else {
+            return false;
+        };
+        diagnostic
+            .sources
+            .as_ref()
+            .is_some_and(|sources| sources.is_stale(source_map))
+    }
+}
+
+impl SourceRecord {
+    pub(crate) fn is_stale(&self, source_map: &CodespanSourceMap) -> bool {
+        self.labels.iter().any(|recorded| {
+            let label = csr_diag::Label::primary(recorded.file_id, recorded.range.clone());
+            hash(source_map, &label).map_or(true, |hash| hash != recorded.hash)
+        })
+    }
+
+    pub(crate) fn emit<W: WriteColor>(
+        &self,
+        writer: &mut W,
+        diagnostic: &CodespanDiagnostic,
+        source_map: &CodespanSourceMap,
+    ) -> Result<(), csr::files::Error> {
+        if self.mode == STALE_MODE_NO_SNIPPET {
+            let loci: Vec<Locus> = (0..diagnostic.diagnostic.labels.len())
+                .map(|index| {
+                    self.locus(index).unwrap_or(Locus {
+                        name: None,
+                        location: None,
+                    })
+                })
+                .collect();
+            return Ok(plain::emit(writer, diagnostic, &loci, &[STALE_NOTE])?);
+        }
+
+        let mut diagnostic = diagnostic.clone();
+        diagnostic.diagnostic.notes.push(STALE_NOTE.to_owned());
+        diagnostic.emit_term(writer, source_map)
+    }
+
+    // Labels added after the record was taken have no known location.
+    pub(crate) fn locus(&self, index: usize) -> Option<Locus> {
+        self.labels.get(index).map(|recorded| Locus {
+            name: Some(recorded.name.clone()),
+            location: Some(recorded.location),
+        })
+    }
+
+    // Follows the labels moved by `codespan_diagnostic_rebase`, given the new
+    // range of each label, or `None` for the ones it dropped.
+    pub(crate) fn rebase(&mut self, ranges: &[Option<LineRange>]) {
+        let mut ranges = ranges.iter();
+        self.labels.retain_mut(|recorded| match ranges.next() {
+            Some(Some(range)) => {
+                recorded.range = range.clone();
+                true
+            }
+            Some(None) => false,
+            None => true,
+        });
+    }
+}
+
+fn hash(
+    source_map: &CodespanSourceMap,
+    label: &csr_diag::Label<FileId>,
+) -> Result<u64, csr::files::Error> {
+    let mut hasher = Fnv1a::new();
+    hasher.write_field(&fingerprint::labelled_lines(source_map, label)?);
+    Ok(hasher.finish())
+}
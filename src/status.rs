@@ -0,0 +1,29 @@
+use codespan_reporting as csr;
+
+// Functions returning a status report 0 on success, the positive error
+// code returned by a writer callback, or one of the negative codes below.
+pub type Status = libc::c_int;
+pub const STATUS_OK: Status = 0;
+pub const STATUS_INVALID_ARGUMENT: Status = -1;
+pub const STATUS_FILE_MISSING: Status = -2;
+pub const STATUS_INVALID_INDEX: Status = -3;
+pub const STATUS_IO_ERROR: Status = -4;
+pub const STATUS_FORMAT_ERROR: Status = -5;
+// Not an error: an emitter dropped the diagnostic because it was suppressed,
+// a duplicate or already in the baseline.
+pub const STATUS_FILTERED: Status = -6;
+
+pub(crate) fn from_error(error: &csr::files::Error) -> Status {
+    match error {
+        csr::files::Error::FileMissing => STATUS_FILE_MISSING,
+        csr::files::Error::IndexTooLarge { .. }
+        | csr::files::Error::LineTooLarge { .. }
+        | csr::files::Error::ColumnTooLarge { .. }
+        | csr::files::Error::InvalidCharBoundary { .. } => STATUS_INVALID_INDEX,
+        csr::files::Error::Io(error) => error
+            .raw_os_error()
+            .filter(|code| *code > 0)
+            .unwrap_or(STATUS_IO_ERROR),
+        _ => STATUS_FORMAT_ERROR,
+    }
+}
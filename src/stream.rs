@@ -0,0 +1,85 @@
+use crate::color::{self, ColorMode};
+use crate::diagnostic::CodespanDiagnostic;
+use crate::source_map::CodespanSourceMap;
+use crate::status::{self, Status, STATUS_INVALID_ARGUMENT, STATUS_OK};
+use codespan_reporting as csr;
+use codespan_reporting::term::termcolor;
+use std::io;
+
+// Returns 0 on success, or a positive error code such as `EPIPE` to abort
+// rendering.
+pub type StreamWriterCallback = unsafe extern "C" fn(
+    user_data: *mut libc::c_void,
+    utf8_output: *const u8,
+    output_length: libc::size_t,
+) -> libc::c_int;
+pub type FlushCallback = unsafe extern "C" fn(user_data: *mut libc::c_void) -> libc::c_int;
+
+pub(crate) struct StreamWriter {
+    user_data: *mut libc::c_void,
+    writer: StreamWriterCallback,
+    flush: Option<FlushCallback>,
+}
+
+impl io::Write for StreamWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !buf.is_empty() {
+            match unsafe { (self.writer)(self.user_data, buf.as_ptr(), buf.len()) } {
+                0 => {}
+                code => return Err(io::Error::from_raw_os_error(code)),
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self
+            .flush
+            .map_or(0, |flush| unsafe { flush(self.user_data) })
+        {
+            0 => Ok(()),
+            code => Err(io::Error::from_raw_os_error(code)),
+        }
+    }
+}
+
+impl CodespanDiagnostic {
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_write_diagnostic_stream(
+        user_data: *mut libc::c_void,
+        diagnostic: *const Self,
+        source_map: *const CodespanSourceMap,
+        color: ColorMode,
+        writer: Option<StreamWriterCallback>,
+        flush: Option<FlushCallback>,
+    ) -> Status {
+        let (Some(diagnostic), Some(writer)) = (unsafe { diagnostic.as_ref() }, writer) else {
+            return STATUS_INVALID_ARGUMENT;
+        };
+        let Some(source_map) = (unsafe { diagnostic.source_map(source_map) }) else {
+            return STATUS_INVALID_ARGUMENT;
+        };
+
+        let stream = StreamWriter {
+            user_data,
+            writer,
+            flush,
+        };
+        let result = if color::enabled(color, None) {
+            diagnostic.stream(&mut termcolor::Ansi::new(stream), source_map)
+        } else {
+            diagnostic.stream(&mut termcolor::NoColor::new(stream), source_map)
+        };
+        result.map_or_else(|error| status::from_error(&error), |()| STATUS_OK)
+    }
+
+    fn stream<W: termcolor::WriteColor>(
+        &self,
+        writer: &mut W,
+        source_map: &CodespanSourceMap,
+    ) -> Result<(), csr::files::Error> {
+        self.render(writer, source_map)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+use crate::diagnostic::CodespanDiagnostic;
+use crate::source_map::CodespanSourceMap;
+use crate::FileId;
+use codespan_reporting as csr;
+use codespan_reporting::diagnostic as csr_diag;
+use codespan_reporting::files::Files;
+use std::ops::Range;
+
+#[repr(C)]
+pub struct CodespanSuppressionSyntax {
+    pub line_comment: *const u8,
+    pub line_comment_length: libc::size_t,
+    pub next_line_marker: *const u8,
+    pub next_line_marker_length: libc::size_t,
+    pub same_line_marker: *const u8,
+    pub same_line_marker_length: libc::size_t,
+    pub file_marker: *const u8,
+    pub file_marker_length: libc::size_t,
+}
+
+#[derive(Clone)]
+pub(crate) struct SuppressionSyntax {
+    line_comment: String,
+    next_line_marker: String,
+    same_line_marker: String,
+    file_marker: String,
+}
+
+impl SuppressionSyntax {
+    pub(crate) unsafe fn from_raw(syntax: *const CodespanSuppressionSyntax) -> Option<Self> {
+        let syntax = unsafe { syntax.as_ref()? };
+        let syntax = Self {
+            line_comment: CodespanDiagnostic::utf8_to_string(
+                syntax.line_comment,
+                syntax.line_comment_length,
+            ),
+            next_line_marker: CodespanDiagnostic::utf8_to_string(
+                syntax.next_line_marker,
+                syntax.next_line_marker_length,
+            ),
+            same_line_marker: CodespanDiagnostic::utf8_to_string(
+                syntax.same_line_marker,
+                syntax.same_line_marker_length,
+            ),
+            file_marker: CodespanDiagnostic::utf8_to_string(
+                syntax.file_marker,
+                syntax.file_marker_length,
+            ),
+        };
+        (!syntax.line_comment.is_empty()).then_some(syntax)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Scope {
+    Line(usize),
+    File,
+}
+
+pub(crate) struct Suppression {
+    scope: Scope,
+    // `None` suppresses every diagnostic in scope, regardless of its code.
+    codes: Option<Vec<String>>,
+    marker: String,
+    range: Range<usize>,
+    used: bool,
+}
+
+// Lines are taken from the source map, so that suppressions end up on the
+// same lines as the diagnostics they are matched against.
+pub(crate) fn scan(
+    source_map: &CodespanSourceMap,
+    file_id: FileId,
+    syntax: &SuppressionSyntax,
+) -> Result<Vec<Suppression>, csr::files::Error> {
+    let source = source_map.source(file_id)?;
+    let last_line = source_map.line_index(file_id, source.len())?;
+    let mut suppressions = Vec::new();
+
+    for line_index in 0..=last_line {
+        let line_range = source_map.line_range(file_id, line_index)?;
+        let Some(line) = source.get(line_range.clone()) else {
+            continue;
+        };
+        let line_start = line_range.start;
+        let line_end = line_start + line.trim_end_matches(['\n', '\r']).len();
+        'comments: for (comment_start, _) in line.match_indices(&syntax.line_comment) {
+            let comment = &source[line_start + comment_start..line_end];
+            let text = comment[syntax.line_comment.len()..].trim_start();
+
+            // The file marker is checked first since it is commonly an
+            // extension of one of the line markers, e.g. `ignore-file`.
+            let markers = [
+                (&syntax.file_marker, Scope::File),
+                (&syntax.next_line_marker, Scope::Line(line_index + 1)),
+                (&syntax.same_line_marker, Scope::Line(line_index)),
+            ];
+            for (marker, scope) in markers {
+                if let Some(codes) = parse_marker(text, marker) {
+                    suppressions.push(Suppression {
+                        scope,
+                        codes,
+                        marker: comment.trim_end().to_owned(),
+                        range: line_start + comment_start..line_end,
+                        used: false,
+                    });
+                    break 'comments;
+                }
+            }
+        }
+    }
+    Ok(suppressions)
+}
+
+fn parse_marker(text: &str, marker: &str) -> Option<Option<Vec<String>>> {
+    if marker.is_empty() {
+        return None;
+    }
+
+    let rest = text.strip_prefix(marker)?;
+    if let Some(codes) = rest.trim_start().strip_prefix(':') {
+        Some(Some(
+            codes
+                .split([',', ' ', '\t'])
+                .filter(|code| !code.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        ))
+    } else if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+        Some(None)
+    } else {
+        None
+    }
+}
+
+pub(crate) fn suppress(
+    suppressions: &mut [Suppression],
+    line_index: usize,
+    code: Option<&str>,
+) -> bool {
+    let suppression = suppressions.iter_mut().find(|suppression| {
+        (suppression.scope == Scope::File || suppression.scope == Scope::Line(line_index))
+            && match (&suppression.codes, code) {
+                (None, _) => true,
+                (Some(codes), Some(code)) => codes.iter().any(|c| c == code),
+                (Some(_), None) => false,
+            }
+    });
+    match suppression {
+        Some(suppression) => {
+            suppression.used = true;
+            true
+        }
+        None => false,
+    }
+}
+
+pub(crate) fn unused_diagnostics(
+    file_id: FileId,
+    suppressions: &[Suppression],
+) -> impl Iterator<Item = csr_diag::Diagnostic<FileId>> {
+    suppressions
+        .iter()
+        .filter(|suppression| !suppression.used)
+        .map(move |suppression| {
+            csr_diag::Diagnostic::warning()
+                .with_message(format!("unused suppression `{}`", suppression.marker))
+                .with_labels(vec![csr_diag::Label::primary(
+                    file_id,
+                    suppression.range.clone(),
+                )
+                .with_message("no diagnostic was suppressed by this comment")])
+        })
+}
@@ -0,0 +1,158 @@
+use crate::color::ColorMode;
+use crate::diagnostic::CodespanDiagnostic;
+use crate::source_map::CodespanSourceMap;
+use crate::status::{self, Status, STATUS_INVALID_ARGUMENT, STATUS_IO_ERROR, STATUS_OK};
+use std::{io, ptr};
+
+impl CodespanDiagnostic {
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_write_diagnostic_fd(
+        fd: libc::c_int,
+        diagnostic: *const Self,
+        source_map: *const CodespanSourceMap,
+        color: ColorMode,
+    ) -> Status {
+        let Some(diagnostic) = (unsafe { diagnostic.as_ref() }) else {
+            return STATUS_INVALID_ARGUMENT;
+        };
+        let Some(source_map) = (unsafe { diagnostic.source_map(source_map) }) else {
+            return STATUS_INVALID_ARGUMENT;
+        };
+
+        // The diagnostic is rendered up front so that it reaches the file with
+        // as few writes as possible and is not interleaved with other output.
+        let result = diagnostic
+            .render_to_vec(source_map, color, Some(fd))
+            .and_then(|utf8_output| Ok(write_fd(fd, &utf8_output)?));
+        result.map_or_else(|error| status::from_error(&error), |()| STATUS_OK)
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_write_diagnostic_file(
+        file: *mut libc::FILE,
+        diagnostic: *const Self,
+        source_map: *const CodespanSourceMap,
+        color: ColorMode,
+    ) -> Status {
+        let (false, Some(diagnostic)) = (file.is_null(), unsafe { diagnostic.as_ref() }) else {
+            return STATUS_INVALID_ARGUMENT;
+        };
+        let Some(source_map) = (unsafe { diagnostic.source_map(source_map) }) else {
+            return STATUS_INVALID_ARGUMENT;
+        };
+
+        let fd = unsafe { libc::fileno(file) };
+        let result = diagnostic
+            .render_to_vec(source_map, color, (fd >= 0).then_some(fd))
+            .and_then(|utf8_output| Ok(unsafe { write_file(file, &utf8_output) }?));
+        result.map_or_else(|error| status::from_error(&error), |()| STATUS_OK)
+    }
+
+    // The returned string is NUL terminated and must be freed with
+    // `codespan_free_string`. Returns null if rendering failed.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_render_diagnostic_to_string(
+        diagnostic: *const Self,
+        source_map: *const CodespanSourceMap,
+        color: ColorMode,
+        output_length: *mut libc::size_t,
+    ) -> *mut u8 {
+        if diagnostic.is_null() {
+            return ptr::null_mut();
+        }
+
+        let diagnostic = unsafe { &*diagnostic };
+        let Some(source_map) = (unsafe { diagnostic.source_map(source_map) }) else {
+            return ptr::null_mut();
+        };
+        let Ok(utf8_output) = diagnostic.render_to_vec(source_map, color, None) else {
+            return ptr::null_mut();
+        };
+
+        let string = unsafe { libc::malloc(utf8_output.len() + 1) } as *mut u8;
+        if !string.is_null() {
+            unsafe {
+                ptr::copy_nonoverlapping(utf8_output.as_ptr(), string, utf8_output.len());
+                *string.add(utf8_output.len()) = 0;
+            }
+            if !output_length.is_null() {
+                unsafe { *output_length = utf8_output.len() };
+            }
+        }
+        string
+    }
+
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_free_string(string: *mut u8) {
+        unsafe { libc::free(string.cast()) }
+    }
+
+    // Works like `snprintf`: at most `buffer_size - 1` bytes are written
+    // followed by a NUL, and the length of the full rendering is returned.
+    // Truncation never splits a UTF-8 character. Returns a negative status if
+    // rendering failed.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn codespan_render_diagnostic_to_buffer(
+        diagnostic: *const Self,
+        source_map: *const CodespanSourceMap,
+        color: ColorMode,
+        buffer: *mut u8,
+        buffer_size: libc::size_t,
+    ) -> libc::ssize_t {
+        if diagnostic.is_null() {
+            return STATUS_INVALID_ARGUMENT as libc::ssize_t;
+        }
+
+        let diagnostic = unsafe { &*diagnostic };
+        let Some(source_map) = (unsafe { diagnostic.source_map(source_map) }) else {
+            return STATUS_INVALID_ARGUMENT as libc::ssize_t;
+        };
+        let utf8_output = match diagnostic.render_to_vec(source_map, color, None) {
+            Ok(utf8_output) => utf8_output,
+            Err(error) => {
+                return match status::from_error(&error) {
+                    status if status < 0 => status,
+                    _ => STATUS_IO_ERROR,
+                } as libc::ssize_t;
+            }
+        };
+
+        if !buffer.is_null() && buffer_size > 0 {
+            let mut length = utf8_output.len().min(buffer_size - 1);
+            while utf8_output
+                .get(length)
+                .is_some_and(|byte| byte & 0xC0 == 0x80)
+            {
+                length -= 1;
+            }
+            unsafe {
+                ptr::copy_nonoverlapping(utf8_output.as_ptr(), buffer, length);
+                *buffer.add(length) = 0;
+            }
+        }
+        utf8_output.len() as libc::ssize_t
+    }
+}
+
+fn write_fd(fd: libc::c_int, mut utf8_output: &[u8]) -> io::Result<()> {
+    while !utf8_output.is_empty() {
+        let written = unsafe { libc::write(fd, utf8_output.as_ptr().cast(), utf8_output.len()) };
+        if written < 0 {
+            let error = io::Error::last_os_error();
+            if error.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(error);
+        }
+        utf8_output = &utf8_output[written as usize..];
+    }
+    Ok(())
+}
+
+unsafe fn write_file(file: *mut libc::FILE, utf8_output: &[u8]) -> io::Result<()> {
+    let written = unsafe { libc::fwrite(utf8_output.as_ptr().cast(), 1, utf8_output.len(), file) };
+    if written < utf8_output.len() || unsafe { libc::fflush(file) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}